@@ -0,0 +1,9 @@
+// Preloaded with `node --require` to run the server from another working directory
+// than the worktree root Zed starts it in.
+const dir = process.env.ZED_SVELTE_CWD;
+
+try {
+  process.chdir(dir);
+} catch (error) {
+  process.stderr.write(`Failed to change the working directory to ${dir}: ${error.message}\n`);
+}
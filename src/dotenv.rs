@@ -0,0 +1,107 @@
+/// Parses the contents of a `.env` file into key/value pairs, in the order they appear.
+///
+/// Blank lines and `#` comments are skipped, as is an `export ` prefix. Values can be
+/// single-quoted (taken as written), double-quoted (with `\n`, `\t`, `\"` and `\\` escapes)
+/// or bare, in which case a ` #` starts a trailing comment. Quoted values don't span lines.
+/// Lines without a `=` or with an unterminated quote are skipped.
+pub fn parse(contents: &str) -> Vec<(String, String)> {
+    contents.lines().filter_map(parse_line).collect()
+}
+
+fn parse_line(line: &str) -> Option<(String, String)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let line = line.strip_prefix("export ").unwrap_or(line);
+
+    let (key, value) = line.split_once('=')?;
+    let key = key.trim();
+    if key.is_empty() || key.contains(char::is_whitespace) {
+        return None;
+    }
+
+    let value = value.trim_start();
+    let value = if let Some(quoted) = value.strip_prefix('\'') {
+        quoted[..quoted.find('\'')?].to_string()
+    } else if let Some(quoted) = value.strip_prefix('"') {
+        unescape(quoted)?
+    } else {
+        let end = value.find(" #").unwrap_or(value.len());
+        value[..end].trim_end().to_string()
+    };
+    Some((key.to_string(), value))
+}
+
+/// Reads a double-quoted value up to its closing quote.
+fn unescape(quoted: &str) -> Option<String> {
+    let mut value = String::new();
+    let mut chars = quoted.chars();
+    loop {
+        match chars.next()? {
+            '"' => return Some(value),
+            '\\' => match chars.next()? {
+                'n' => value.push('\n'),
+                't' => value.push('\t'),
+                'r' => value.push('\r'),
+                c @ ('"' | '\\') => value.push(c),
+                c => value.extend(['\\', c]),
+            },
+            c => value.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variable(key: &str, value: &str) -> (String, String) {
+        (key.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn parses_a_representative_env_file() {
+        let contents = r#"
+# Database
+DATABASE_URL=postgres://localhost:5432/app
+export PUBLIC_API_URL = https://api.example.com # the public one
+
+SECRET='single # quoted $value'
+GREETING="Hello,\n\"world\""
+EMPTY=
+"#;
+        assert_eq!(
+            parse(contents),
+            [
+                variable("DATABASE_URL", "postgres://localhost:5432/app"),
+                variable("PUBLIC_API_URL", "https://api.example.com"),
+                variable("SECRET", "single # quoted $value"),
+                variable("GREETING", "Hello,\n\"world\""),
+                variable("EMPTY", ""),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_hashes_inside_bare_values() {
+        assert_eq!(parse("COLOR=#ff0000"), [variable("COLOR", "#ff0000")]);
+        assert_eq!(parse("URL=a#b # comment"), [variable("URL", "a#b")]);
+    }
+
+    #[test]
+    fn keeps_unknown_escapes_and_drops_text_after_the_quote() {
+        assert_eq!(
+            parse(r#"PATTERN="a\d+" trailing"#),
+            [variable("PATTERN", r"a\d+")]
+        );
+    }
+
+    #[test]
+    fn skips_invalid_lines() {
+        assert_eq!(
+            parse("no equals sign\n=value\nTWO WORDS=x\nOPEN=\"unterminated\nOK=1"),
+            [variable("OK", "1")]
+        );
+    }
+}
@@ -0,0 +1,121 @@
+use crate::settings::SvelteSettings;
+use std::time::{Duration, Instant};
+
+/// How many launches within [`CRASH_LOOP_WINDOW`] are considered a crash loop.
+const CRASH_LOOP_LAUNCHES: usize = 5;
+const CRASH_LOOP_WINDOW: Duration = Duration::from_secs(60);
+
+/// Recent launches of the server for a worktree, used to detect a server that keeps
+/// crashing on startup.
+#[derive(Default)]
+pub struct Launches {
+    settings: SvelteSettings,
+    times: Vec<Instant>,
+}
+
+impl Launches {
+    /// Returns an error if the server is crash looping with `settings` and shouldn't be
+    /// launched again yet.
+    ///
+    /// Launches are forgotten once they're older than the window, so a long-lived run or
+    /// waiting it out resets the count, as does changing the settings.
+    pub fn check(&mut self, settings: &SvelteSettings, now: Instant) -> Result<(), String> {
+        if &self.settings != settings {
+            self.settings = settings.clone();
+            self.times.clear();
+        }
+
+        self.times
+            .retain(|launch| now.duration_since(*launch) < CRASH_LOOP_WINDOW);
+        if self.times.len() >= CRASH_LOOP_LAUNCHES {
+            return Err(format!(
+                "the server was started {} times in the last {} seconds and is likely crashing on \
+                 startup, check the server logs (an incompatible Node.js is a common cause). \
+                 The installation is checked again on the next start, after changing the \
+                 settings or waiting a minute",
+                self.times.len(),
+                CRASH_LOOP_WINDOW.as_secs()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Records a launch, once a command to start the server was built. Attempts that fail
+    /// before that, like a failed download, aren't launches.
+    pub fn record(&mut self, now: Instant) {
+        self.times.push(now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn launch(
+        launches: &mut Launches,
+        settings: &SvelteSettings,
+        now: Instant,
+    ) -> Result<(), String> {
+        launches.check(settings, now)?;
+        launches.record(now);
+        Ok(())
+    }
+
+    #[test]
+    fn stops_launching_after_quick_restarts() {
+        let settings = SvelteSettings::default();
+        let mut launches = Launches::default();
+        let start = Instant::now();
+        for second in 0..CRASH_LOOP_LAUNCHES as u64 {
+            let now = start + Duration::from_secs(second);
+            assert!(launch(&mut launches, &settings, now).is_ok());
+        }
+        assert!(launch(&mut launches, &settings, start + Duration::from_secs(10)).is_err());
+    }
+
+    #[test]
+    fn failed_attempts_are_not_launches() {
+        let settings = SvelteSettings::default();
+        let mut launches = Launches::default();
+        let now = Instant::now();
+        for _ in 0..CRASH_LOOP_LAUNCHES * 2 {
+            assert!(launches.check(&settings, now).is_ok());
+        }
+    }
+
+    #[test]
+    fn changing_the_settings_resets_the_count() {
+        let settings = SvelteSettings::default();
+        let mut launches = Launches::default();
+        let now = Instant::now();
+        for _ in 0..CRASH_LOOP_LAUNCHES {
+            launch(&mut launches, &settings, now).unwrap();
+        }
+
+        let changed = SvelteSettings {
+            max_memory_mb: Some(4096),
+            ..SvelteSettings::default()
+        };
+        assert!(launch(&mut launches, &changed, now).is_ok());
+    }
+
+    #[test]
+    fn a_long_lived_run_resets_the_count() {
+        let settings = SvelteSettings::default();
+        let mut launches = Launches::default();
+        let start = Instant::now();
+        for _ in 0..CRASH_LOOP_LAUNCHES - 1 {
+            launch(&mut launches, &settings, start).unwrap();
+        }
+        // The last of these runs for ten minutes before exiting
+        launch(&mut launches, &settings, start + Duration::from_secs(1)).unwrap();
+
+        let later = start + Duration::from_secs(600);
+        assert!(launch(&mut launches, &settings, later).is_ok());
+        for second in 1..CRASH_LOOP_LAUNCHES as u64 {
+            let now = later + Duration::from_secs(second);
+            assert!(launch(&mut launches, &settings, now).is_ok());
+        }
+        assert!(launch(&mut launches, &settings, later + Duration::from_secs(10)).is_err());
+    }
+}
@@ -0,0 +1,117 @@
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+/// How many symlinks are followed before giving up on a path, as a loop never resolves.
+const MAX_LINKS: usize = 40;
+
+/// Resolves the symlinks in `path`, component by component, so a package symlinked into
+/// `node_modules` (as pnpm does) has a single real path. Components that aren't symlinks or
+/// can't be read are kept as they are, and the raw path is returned for a symlink loop.
+///
+/// This stands in for `fs::canonicalize`, which isn't supported on WASI.
+pub fn resolve(path: &Path) -> PathBuf {
+    let mut resolved = PathBuf::new();
+    // The components left to resolve, the next one last
+    let mut rest: Vec<PathBuf> = reversed_components(path);
+    let mut links = 0;
+
+    while let Some(component) = rest.pop() {
+        match component.components().next() {
+            Some(Component::Normal(name)) => {
+                let candidate = resolved.join(name);
+                match fs::read_link(&candidate) {
+                    Ok(_) if links == MAX_LINKS => return path.to_path_buf(),
+                    // A relative target resolves against the link's directory, which is
+                    // `resolved`, and an absolute one replaces it once its root is reached
+                    Ok(target) => {
+                        links += 1;
+                        rest.extend(reversed_components(&target));
+                    }
+                    Err(_) => resolved = candidate,
+                }
+            }
+            Some(Component::ParentDir) => {
+                if resolved.file_name().is_some() {
+                    resolved.pop();
+                } else if !resolved.has_root() {
+                    resolved.push("..");
+                }
+            }
+            Some(root @ (Component::Prefix(_) | Component::RootDir)) => resolved.push(root),
+            Some(Component::CurDir) | None => {}
+        }
+    }
+
+    resolved
+}
+
+fn reversed_components(path: &Path) -> Vec<PathBuf> {
+    path.components()
+        .rev()
+        .map(|component| PathBuf::from(component.as_os_str()))
+        .collect()
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    /// Creates an empty directory for a test's symlink fixture.
+    fn fixture(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("svelte-links-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        // The temporary directory can be behind a symlink itself, like on macOS
+        fs::canonicalize(dir).unwrap()
+    }
+
+    #[test]
+    fn resolves_a_pnpm_style_package_link() {
+        let dir = fixture("pnpm");
+        let store = dir.join("node_modules/.pnpm/typescript-svelte-plugin@0.3.0/node_modules");
+        fs::create_dir_all(store.join("typescript-svelte-plugin")).unwrap();
+        symlink(
+            ".pnpm/typescript-svelte-plugin@0.3.0/node_modules/typescript-svelte-plugin",
+            dir.join("node_modules/typescript-svelte-plugin"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            resolve(&dir.join("node_modules/typescript-svelte-plugin")),
+            store.join("typescript-svelte-plugin")
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn resolves_chained_and_absolute_links() {
+        let dir = fixture("chained");
+        fs::create_dir_all(dir.join("real/package")).unwrap();
+        symlink(dir.join("real"), dir.join("absolute")).unwrap();
+        symlink("absolute/package", dir.join("chained")).unwrap();
+        symlink("../real", dir.join("real/parent")).unwrap();
+
+        let real = dir.join("real/package");
+        assert_eq!(resolve(&dir.join("chained")), real);
+        assert_eq!(resolve(&dir.join("chained/../package")), real);
+        assert_eq!(resolve(&dir.join("real/parent/package")), real);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_missing_paths_and_link_loops() {
+        let dir = fixture("loop");
+        symlink("b", dir.join("a")).unwrap();
+        symlink("a", dir.join("b")).unwrap();
+
+        assert_eq!(resolve(&dir.join("a/package")), dir.join("a/package"));
+        assert_eq!(
+            resolve(&dir.join("missing/package")),
+            dir.join("missing/package")
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
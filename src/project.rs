@@ -0,0 +1,114 @@
+use crate::vscode;
+use std::collections::BTreeSet;
+use zed_extension_api::{self as zed, serde_json};
+
+const ZED_SETTINGS_PATH: &str = ".zed/settings.json";
+
+fn package_json(worktree: &zed::Worktree) -> Option<serde_json::Value> {
+    let package_json = worktree.read_text_file("package.json").ok()?;
+    serde_json::from_str(&package_json).ok()
+}
+
+/// Returns the keys of `lsp.<server_name>.settings` that the worktree's own
+/// `.zed/settings.json` sets, which come from the project rather than from the user.
+pub fn lsp_settings_keys(worktree: &zed::Worktree, server_name: &str) -> BTreeSet<String> {
+    let Ok(contents) = worktree.read_text_file(ZED_SETTINGS_PATH) else {
+        return BTreeSet::new();
+    };
+    let Ok(settings) = serde_json::from_str::<serde_json::Value>(&vscode::strip_jsonc(&contents))
+    else {
+        return BTreeSet::new();
+    };
+
+    match &settings["lsp"][server_name]["settings"] {
+        serde_json::Value::Object(settings) => settings.keys().cloned().collect(),
+        _ => BTreeSet::new(),
+    }
+}
+
+/// Returns whether the worktree pins its toolchain with Volta and Volta is installed.
+pub fn uses_volta(worktree: &zed::Worktree) -> bool {
+    package_json(worktree).is_some_and(|package_json| package_json["volta"].is_object())
+        && worktree.which("volta").is_some()
+}
+
+/// Returns whether the worktree has a Tailwind CSS config, whose directives like `@tailwind`
+/// and `@apply` are unknown at-rules to the CSS service.
+pub fn uses_tailwind(worktree: &zed::Worktree) -> bool {
+    ["js", "cjs", "mjs", "ts"].iter().any(|extension| {
+        worktree
+            .read_text_file(&format!("tailwind.config.{extension}"))
+            .is_ok()
+    })
+}
+
+/// Returns the version range the worktree's `package.json` declares for `package_name`,
+/// looking at both regular and dev dependencies.
+pub fn dependency_version(worktree: &zed::Worktree, package_name: &str) -> Option<String> {
+    declared_version(&package_json(worktree)?, package_name).map(ToString::to_string)
+}
+
+fn declared_version<'a>(
+    package_json: &'a serde_json::Value,
+    package_name: &str,
+) -> Option<&'a str> {
+    ["dependencies", "devDependencies"]
+        .iter()
+        .find_map(|field| package_json[field][package_name].as_str())
+}
+
+/// Returns the version of `package_name` installed in the worktree's `node_modules`.
+pub fn installed_version(worktree: &zed::Worktree, package_name: &str) -> Option<String> {
+    let package_json = worktree
+        .read_text_file(&format!("node_modules/{package_name}/package.json"))
+        .ok()?;
+    let package_json: serde_json::Value = serde_json::from_str(&package_json).ok()?;
+    package_json["version"].as_str().map(ToString::to_string)
+}
+
+/// Returns the major version of Svelte the worktree depends on, taken from the start of the
+/// declared range (so `^5.1.0` and `5.0.0-next.1` are both 5).
+pub fn svelte_major_version(worktree: &zed::Worktree) -> Option<u64> {
+    range_major(&dependency_version(worktree, "svelte")?)
+}
+
+fn range_major(range: &str) -> Option<u64> {
+    let major: String = range
+        .trim_start_matches(|c: char| !c.is_ascii_digit())
+        .chars()
+        .take_while(char::is_ascii_digit)
+        .collect();
+    major.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn range_major_reads_the_start_of_the_range() {
+        assert_eq!(range_major("^5.1.0"), Some(5));
+        assert_eq!(range_major("~4.2.19"), Some(4));
+        assert_eq!(range_major("5.0.0-next.1"), Some(5));
+        assert_eq!(range_major(">=4 <6"), Some(4));
+        assert_eq!(range_major("5.x"), Some(5));
+    }
+
+    #[test]
+    fn range_major_is_none_without_a_version() {
+        assert_eq!(range_major("latest"), None);
+        assert_eq!(range_major("workspace:*"), None);
+        assert_eq!(range_major(""), None);
+    }
+
+    #[test]
+    fn declared_version_looks_at_dev_dependencies_too() {
+        let package_json = serde_json::json!({
+            "dependencies": { "svelte": "^5.1.0" },
+            "devDependencies": { "vite": "^6.0.0" },
+        });
+        assert_eq!(declared_version(&package_json, "svelte"), Some("^5.1.0"));
+        assert_eq!(declared_version(&package_json, "vite"), Some("^6.0.0"));
+        assert_eq!(declared_version(&package_json, "typescript"), None);
+    }
+}
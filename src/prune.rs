@@ -0,0 +1,88 @@
+use crate::log::{info, warn};
+use std::{fs, io, path::Path};
+use zed_extension_api::serde_json;
+
+/// Removes the packages in `work_dir`'s `node_modules` that npm's lockfile no longer lists,
+/// which are left behind by updates that changed the dependency tree.
+///
+/// Nothing is removed if the lockfile can't be read, since then there's no way to tell which
+/// packages are still in use.
+pub fn prune_stale_packages(work_dir: &Path) {
+    let Some(locked) = locked_packages(work_dir) else {
+        return;
+    };
+
+    let node_modules = work_dir.join("node_modules");
+    let mut reclaimed = 0;
+    for package in installed_packages(&node_modules) {
+        if locked.contains_key(&format!("node_modules/{package}")) {
+            continue;
+        }
+
+        let path = node_modules.join(&package);
+        let size = dir_size(&path).unwrap_or(0);
+        match fs::remove_dir_all(&path) {
+            Ok(()) => reclaimed += size,
+            Err(error) => warn!("Failed to remove stale package {package}: {error}"),
+        }
+    }
+
+    if reclaimed > 0 {
+        info!("Removed stale packages, reclaiming {} KB", reclaimed / 1024);
+    }
+}
+
+fn locked_packages(work_dir: &Path) -> Option<serde_json::Map<String, serde_json::Value>> {
+    let lockfile = fs::read_to_string(work_dir.join("package-lock.json")).ok()?;
+    let mut lockfile: serde_json::Value = serde_json::from_str(&lockfile).ok()?;
+    match lockfile["packages"].take() {
+        serde_json::Value::Object(packages) => Some(packages),
+        _ => None,
+    }
+}
+
+/// Lists the top-level packages in `node_modules`, including scoped ones as `@scope/name`.
+fn installed_packages(node_modules: &Path) -> Vec<String> {
+    let mut packages = Vec::new();
+    for name in dir_names(node_modules) {
+        // Skip npm's own bookkeeping, like `.bin` and `.package-lock.json`
+        if name.starts_with('.') {
+            continue;
+        }
+        if name.starts_with('@') {
+            packages.extend(
+                dir_names(&node_modules.join(&name))
+                    .into_iter()
+                    .map(|package| format!("{name}/{package}")),
+            );
+        } else {
+            packages.push(name);
+        }
+    }
+    packages
+}
+
+fn dir_names(path: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(path) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_dir()))
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect()
+}
+
+fn dir_size(path: &Path) -> io::Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            size += dir_size(&entry.path())?;
+        } else {
+            size += entry.metadata()?.len();
+        }
+    }
+    Ok(size)
+}
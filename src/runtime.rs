@@ -0,0 +1,346 @@
+use crate::{
+    log::{info, warn},
+    version,
+};
+use std::path::{Path, PathBuf};
+use zed_extension_api::{self as zed, Result};
+
+/// A JavaScript runtime that can run the language server.
+#[derive(Clone, Copy, Debug)]
+pub enum Runtime {
+    /// The Node.js binary managed by Zed.
+    Node,
+    /// A `node` binary found on the worktree's `PATH`, e.g. one picked by a version manager.
+    SystemNode,
+    /// A `bun` binary found on the worktree's `PATH`.
+    Bun,
+}
+
+/// How the server gets launched.
+pub struct LaunchOptions<'a> {
+    /// Options passed to Node.js ahead of the script.
+    pub node_args: &'a [String],
+    /// The server's `engines.node` range, if known.
+    pub node_requirement: Option<&'a str>,
+    /// Try a `node` on the worktree's `PATH` before Zed's Node.js.
+    pub prefer_system_node: bool,
+    /// Command and arguments to run the runtime through, like `nice -n 10`.
+    pub wrapper: &'a [String],
+}
+
+impl Runtime {
+    /// Runtimes in the order they are tried.
+    fn fallback_chain(prefer_system_node: bool) -> [Runtime; 3] {
+        if prefer_system_node {
+            [Runtime::SystemNode, Runtime::Node, Runtime::Bun]
+        } else {
+            [Runtime::Node, Runtime::SystemNode, Runtime::Bun]
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Runtime::Node => "Zed's Node.js",
+            Runtime::SystemNode => "node on PATH",
+            Runtime::Bun => "bun",
+        }
+    }
+
+    fn binary_path(
+        self,
+        worktree: &zed::Worktree,
+        node_requirement: Option<&str>,
+    ) -> Result<String> {
+        match self {
+            Runtime::Node => {
+                let path = zed::node_binary_path()?;
+                check_node_version(&path, node_requirement)?;
+                Ok(path)
+            }
+            Runtime::SystemNode => {
+                let path = worktree
+                    .which("node")
+                    .ok_or_else(|| "not found on PATH".to_string())?;
+                check_node_version(&path, node_requirement)?;
+                Ok(path)
+            }
+            Runtime::Bun => worktree
+                .which("bun")
+                .ok_or_else(|| match bun_install_path(worktree) {
+                    Some(path) => format!("not found on PATH, and {path} is unverified"),
+                    None => "not found on PATH".to_string(),
+                }),
+        }
+    }
+
+    fn server_command(
+        self,
+        worktree: &zed::Worktree,
+        server_path: &str,
+        options: &LaunchOptions,
+    ) -> Result<zed::Command> {
+        let node_args = options.node_args;
+        let mut args = Vec::new();
+        match self {
+            Runtime::Node | Runtime::SystemNode => args.extend_from_slice(node_args),
+            Runtime::Bun if !node_args.is_empty() => {
+                warn!("Ignoring Node.js options {node_args:?}, they don't apply to bun");
+            }
+            Runtime::Bun => {}
+        }
+        args.extend([server_path.to_string(), "--stdio".to_string()]);
+
+        Ok(zed::Command {
+            command: self.binary_path(worktree, options.node_requirement)?,
+            args,
+            env: Default::default(),
+        })
+    }
+}
+
+/// Where bun's installer puts it, as environment variables and the directory below them.
+const BUN_INSTALL_DIRS: &[(&str, &str)] = &[
+    ("BUN_INSTALL", "bin"),
+    ("HOME", ".bun/bin"),
+    ("USERPROFILE", ".bun/bin"),
+];
+
+/// Guesses where bun's installer put bun, for when it isn't on the worktree's `PATH`.
+///
+/// The extension can't check that the file exists, so the guess is only named in the error
+/// rather than run, which would fail with a less helpful error if it isn't there.
+fn bun_install_path(worktree: &zed::Worktree) -> Option<String> {
+    let binary = match zed::current_platform().0 {
+        zed::Os::Windows => "bun.exe",
+        _ => "bun",
+    };
+    let path = bun_install_location(&worktree.shell_env(), binary)?;
+    Some(path.to_string_lossy().to_string())
+}
+
+/// Returns where bun's installer would have put `binary`, going by the first variable of
+/// [`BUN_INSTALL_DIRS`] that's set in `env`.
+fn bun_install_location(env: &[(String, String)], binary: &str) -> Option<PathBuf> {
+    BUN_INSTALL_DIRS.iter().find_map(|(env_var, dir)| {
+        let (_, root) = env.iter().find(|(key, _)| key == env_var)?;
+        Some(Path::new(root).join(dir).join(binary))
+    })
+}
+
+/// Fails if the Node.js at `path` is known not to satisfy `requirement`.
+///
+/// Running `node --version` would need a `process:exec` capability for every `node` the
+/// server might run with, so this deliberately relies on the version in the path of the
+/// install instead (e.g. Zed's `.../node-v22.5.1-linux-x64/bin/node`) and lets anything it
+/// can't tell the version of through, like a system-wide `/usr/bin/node`.
+fn check_node_version(path: &str, requirement: Option<&str>) -> Result<()> {
+    let (Some(requirement), Some(node_version)) = (requirement, node_version(path)) else {
+        return Ok(());
+    };
+
+    if version::satisfies(node_version, requirement) == Some(false) {
+        return Err(format!(
+            "Node.js {node_version} doesn't satisfy the server's required Node.js {requirement}"
+        ));
+    }
+    Ok(())
+}
+
+fn node_version(path: &str) -> Option<&str> {
+    let start = path.rfind("node-v")? + "node-v".len();
+    let version = &path[start..];
+    let end = version.find(['-', '/', '\\']).unwrap_or(version.len());
+    Some(&version[..end])
+}
+
+/// Builds the command running the server at `server_path` with the first runtime that
+/// is available.
+pub fn server_command(
+    worktree: &zed::Worktree,
+    server_path: &str,
+    options: &LaunchOptions,
+) -> Result<zed::Command> {
+    let chain = Runtime::fallback_chain(options.prefer_system_node);
+    match first_available(chain, |runtime| {
+        runtime.server_command(worktree, server_path, options)
+    }) {
+        Ok(command) => wrap(worktree, command, options.wrapper),
+        Err(errors) => Err(format!(
+            "failed to find a JavaScript runtime for {server_path} (tried {}). \
+             Install Node.js or set `node.path` in your Zed settings to an executable Node.js binary.",
+            errors.join("; ")
+        )),
+    }
+}
+
+/// Builds a command with the first runtime in `chain` that `build` succeeds for, or returns
+/// why each of them failed.
+fn first_available(
+    chain: [Runtime; 3],
+    mut build: impl FnMut(Runtime) -> Result<zed::Command>,
+) -> std::result::Result<zed::Command, Vec<String>> {
+    let mut errors = Vec::new();
+
+    for runtime in chain {
+        match build(runtime) {
+            Ok(command) => {
+                if !errors.is_empty() {
+                    info!("Falling back to {} to run the server", runtime.name());
+                }
+                return Ok(command);
+            }
+            Err(error) => {
+                info!("{} is unavailable: {error}", runtime.name());
+                errors.push(format!("{}: {error}", runtime.name()));
+            }
+        }
+    }
+    Err(errors)
+}
+
+/// Runs `command` through `wrapper`, if there is one.
+fn wrap(
+    worktree: &zed::Worktree,
+    command: zed::Command,
+    wrapper: &[String],
+) -> Result<zed::Command> {
+    let Some((program, wrapper_args)) = wrapper.split_first() else {
+        return Ok(command);
+    };
+
+    let program = if Path::new(program).is_absolute() {
+        program.clone()
+    } else {
+        worktree
+            .which(program)
+            .ok_or_else(|| format!("the server's wrapper {program} wasn't found on PATH"))?
+    };
+    info!(
+        "Running the server through {program} {}",
+        wrapper_args.join(" ")
+    );
+
+    let mut args = wrapper_args.to_vec();
+    args.push(command.command);
+    args.extend(command.args);
+    Ok(zed::Command {
+        command: program,
+        args,
+        env: command.env,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ZED_NODE: &str = "/home/me/.local/share/zed/node/node-v22.5.1-linux-x64/bin/node";
+
+    #[test]
+    fn node_version_comes_from_the_install_path() {
+        assert_eq!(node_version(ZED_NODE), Some("22.5.1"));
+        assert_eq!(
+            node_version(
+                "C:\\Users\\me\\AppData\\Local\\Zed\\node\\node-v20.11.0-win-x64\\node.exe"
+            ),
+            Some("20.11.0")
+        );
+        assert_eq!(node_version("/usr/bin/node"), None);
+    }
+
+    #[test]
+    fn node_that_satisfies_the_requirement_passes() {
+        assert_eq!(check_node_version(ZED_NODE, Some(">=18.0.0")), Ok(()));
+        assert_eq!(check_node_version(ZED_NODE, None), Ok(()));
+    }
+
+    #[test]
+    fn node_that_is_too_old_fails_naming_both_versions() {
+        let error = check_node_version(ZED_NODE, Some(">=24")).unwrap_err();
+        assert!(error.contains("22.5.1"), "{error}");
+        assert!(error.contains(">=24"), "{error}");
+    }
+
+    #[test]
+    fn node_of_unknown_version_passes() {
+        assert_eq!(check_node_version("/usr/bin/node", Some(">=24")), Ok(()));
+        assert_eq!(check_node_version(ZED_NODE, Some("not a range")), Ok(()));
+    }
+
+    fn command(program: &str) -> Result<zed::Command> {
+        Ok(zed::Command {
+            command: program.to_string(),
+            args: Vec::new(),
+            env: Vec::new(),
+        })
+    }
+
+    #[test]
+    fn falls_back_to_bun_when_node_is_unavailable() {
+        let command = first_available(Runtime::fallback_chain(false), |runtime| match runtime {
+            Runtime::Node => Err("Node.js isn't downloaded yet".to_string()),
+            Runtime::SystemNode => Err("not found on PATH".to_string()),
+            Runtime::Bun => command("/home/me/.bun/bin/bun"),
+        });
+        assert_eq!(command.unwrap().command, "/home/me/.bun/bin/bun");
+    }
+
+    #[test]
+    fn prefers_the_system_node_when_asked() {
+        let command = first_available(Runtime::fallback_chain(true), |runtime| match runtime {
+            Runtime::Node => command(ZED_NODE),
+            Runtime::SystemNode => command("/usr/bin/node"),
+            Runtime::Bun => command("bun"),
+        });
+        assert_eq!(command.unwrap().command, "/usr/bin/node");
+    }
+
+    #[test]
+    fn reports_why_each_runtime_failed() {
+        let errors = first_available(Runtime::fallback_chain(false), |_| {
+            Err("not found on PATH".to_string())
+        })
+        .err()
+        .unwrap();
+        assert_eq!(
+            errors,
+            [
+                "Zed's Node.js: not found on PATH",
+                "node on PATH: not found on PATH",
+                "bun: not found on PATH",
+            ]
+        );
+    }
+
+    fn env(variables: &[(&str, &str)]) -> Vec<(String, String)> {
+        variables
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn bun_install_location_prefers_bun_install() {
+        let env = env(&[("HOME", "/home/me"), ("BUN_INSTALL", "/opt/bun")]);
+        assert_eq!(
+            bun_install_location(&env, "bun"),
+            Some(PathBuf::from("/opt/bun/bin/bun"))
+        );
+    }
+
+    #[test]
+    fn bun_install_location_falls_back_to_the_home_directory() {
+        assert_eq!(
+            bun_install_location(&env(&[("HOME", "/home/me")]), "bun"),
+            Some(PathBuf::from("/home/me/.bun/bin/bun"))
+        );
+        assert_eq!(
+            bun_install_location(&env(&[("USERPROFILE", "C:\\Users\\me")]), "bun.exe"),
+            Some(Path::new("C:\\Users\\me").join(".bun/bin/bun.exe"))
+        );
+        assert_eq!(
+            bun_install_location(&env(&[("PATH", "/usr/bin")]), "bun"),
+            None
+        );
+    }
+}
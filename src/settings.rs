@@ -0,0 +1,1129 @@
+use crate::{
+    log::{self, warn, LogLevel},
+    project,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use zed_extension_api::{
+    self as zed,
+    serde_json::{self, json, Map, Value},
+    settings::LspSettings,
+    Result,
+};
+
+/// Extension settings, read from `lsp.svelte-language-server.settings`.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct SvelteSettings {
+    /// Path to the server entrypoint to run instead of an installed one. Takes precedence
+    /// over the `ZED_SVELTE_LS_PATH` environment variable.
+    pub server_path: Option<String>,
+    /// Path to the `typescript-svelte-plugin` package to use instead of an installed one.
+    /// Takes precedence over the `ZED_SVELTE_TS_PLUGIN_PATH` environment variable.
+    pub ts_plugin_path: Option<String>,
+    /// Path of the server entrypoint inside the `svelte-language-server` package, overriding
+    /// the one its `package.json` names.
+    pub server_entry: Option<String>,
+    /// Path of the `lib` directory of the TypeScript the server uses, relative to the worktree.
+    pub tsdk: Option<String>,
+    /// Have the server use the worktree's own TypeScript when `tsdk` isn't set. Defaults to
+    /// `true`.
+    pub use_workspace_typescript: Option<bool>,
+    /// Tell the server when the TypeScript plugin is available. Defaults to `true`.
+    pub enable_ts_plugin: Option<bool>,
+    /// `node_modules`-style directory to take the server and TypeScript plugin from instead of
+    /// installing them. Relative paths resolve against the worktree.
+    pub modules_dir: Option<String>,
+    /// Tarball (or package directory) to install the server from instead of the registry.
+    pub server_tarball: Option<String>,
+    /// Never update packages that are already installed. Defaults to `false`.
+    pub freeze: Option<bool>,
+    /// Log a notice with the old and new versions when a package gets updated. Defaults to
+    /// `true`.
+    pub notify_updates: Option<bool>,
+    /// Run the server version that the last update replaced. Defaults to `false`.
+    pub use_previous_version: Option<bool>,
+    /// Install the TypeScript plugin when the server starts, even if no TypeScript server
+    /// asked for it. Defaults to `false`.
+    pub always_install_ts_plugin: Option<bool>,
+    /// Use the worktree's own `svelte-language-server` when it has one. Defaults to `true`.
+    pub prefer_local: Option<bool>,
+    /// Allow running server code that comes from the worktree itself. Defaults to `false`.
+    pub trust_workspace: Option<bool>,
+    /// File the server's stderr is copied to, truncated on every start. Relative paths resolve
+    /// against the worktree.
+    pub log_file: Option<String>,
+    /// Working directory to run the server from. Relative paths resolve against the worktree.
+    pub cwd: Option<String>,
+    /// Run the server with the `node` on the worktree's `PATH` instead of Zed's Node.js.
+    /// Defaults to `false`.
+    pub use_system_node: Option<bool>,
+    /// Command and arguments the server is run through, like `["nice", "-n", "10"]`.
+    pub wrapper: Vec<String>,
+    /// Extra environment variables for the server, over those of `env_file`.
+    pub env: BTreeMap<String, String>,
+    /// Load the variables of `env_file` into the server's environment. Defaults to `false`.
+    pub load_env_file: Option<bool>,
+    /// The `.env` file loaded by `load_env_file`, relative to the worktree. Defaults to `.env`.
+    pub env_file: Option<String>,
+    /// Heap limit for the server process in MB, passed to Node.js as `--max-old-space-size`.
+    pub max_memory_mb: Option<i64>,
+    /// Remove packages that installs no longer use. Defaults to `true`.
+    pub clean_up_installs: Option<bool>,
+    /// Use the `svelte.*` settings of the worktree's `.vscode/settings.json` where these
+    /// settings leave them unset. Defaults to `false`.
+    pub import_vscode_settings: Option<bool>,
+    /// How much the extension logs.
+    pub log_level: Option<LogLevel>,
+    /// How much the server logs.
+    pub trace: Option<Trace>,
+    /// Show completion lists the server marks as incomplete without filtering them.
+    /// Defaults to `true`.
+    pub dont_filter_incomplete_completions: Option<bool>,
+    /// Let the server format documents. Turning it off leaves formatting to other formatters,
+    /// like Prettier. Defaults to `true`.
+    pub provide_formatter: Option<bool>,
+    /// Toggles for the server's HTML plugin (`svelte.plugin.html`).
+    pub html: HtmlPluginSettings,
+    /// Toggles for the server's CSS plugin (`svelte.plugin.css`).
+    pub css: CssPluginSettings,
+    /// Toggles for the server's Svelte plugin (`svelte.plugin.svelte`).
+    pub svelte: SveltePluginSettings,
+    /// Toggles for the server's TypeScript plugin (`svelte.plugin.typescript`).
+    pub typescript_plugin: TypescriptPluginSettings,
+    /// Emmet configuration forwarded to the server's HTML service, using the same keys as
+    /// VS Code's `emmet.*` settings.
+    pub emmet: Option<Map<String, Value>>,
+    /// Prettier options the server formats with when the project has no Prettier config of
+    /// its own, using Prettier's option names.
+    pub prettier: Option<Map<String, Value>>,
+    /// Options for the CSS service in `<style lang="scss">` blocks, using the same keys as
+    /// VS Code's `scss.*` settings.
+    pub scss: Option<Map<String, Value>>,
+    /// Like `scss`, for `<style lang="less">` blocks.
+    pub less: Option<Map<String, Value>>,
+    pub semantic_tokens: SemanticTokensSettings,
+    /// Whether the server provides document symbols, for every plugin that has them.
+    pub document_symbols: Option<bool>,
+    pub selection_range: SelectionRangeSettings,
+    /// Preferences for `<script lang="ts">` blocks.
+    pub typescript: ScriptSettings,
+    /// Preferences for plain `<script>` blocks.
+    pub javascript: ScriptSettings,
+    /// The settings above that the worktree's own `.zed/settings.json` sets.
+    #[serde(skip)]
+    pub project_keys: BTreeSet<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct SemanticTokensSettings {
+    /// Whether the server provides semantic tokens for script blocks and template expressions.
+    pub enable: Option<bool>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct SelectionRangeSettings {
+    /// Whether the server provides selection ranges, for every plugin that has them.
+    pub enable: Option<bool>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ScriptSettings {
+    pub preferences: ScriptPreferences,
+    pub suggest: ScriptSuggestSettings,
+    pub inlay_hints: InlayHintSettings,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ScriptPreferences {
+    /// How the module specifier of auto-imports is written.
+    pub import_module_specifier: Option<ImportModuleSpecifier>,
+    /// Which quotes auto-imports and other inserted code use.
+    pub quote_style: Option<QuoteStyle>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ScriptSuggestSettings {
+    /// Offer completions that add an import for exports of other modules.
+    pub auto_imports: Option<bool>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct InlayHintSettings {
+    pub parameter_names: ParameterNameHints,
+    pub parameter_types: InlayHintToggle,
+    pub variable_types: VariableTypeHints,
+    pub property_declaration_types: InlayHintToggle,
+    pub function_like_return_types: InlayHintToggle,
+    pub enum_member_values: InlayHintToggle,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct InlayHintToggle {
+    pub enabled: Option<bool>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ParameterNameHints {
+    /// Which arguments get a hint with the name of their parameter.
+    pub enabled: Option<ParameterNameHintsMode>,
+    /// Skip the hint when the argument is a variable named like the parameter.
+    pub suppress_when_argument_matches_name: Option<bool>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct VariableTypeHints {
+    pub enabled: Option<bool>,
+    /// Skip the hint when the variable is named like its type.
+    pub suppress_when_type_matches_name: Option<bool>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ParameterNameHintsMode {
+    None,
+    Literals,
+    All,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Trace {
+    #[default]
+    Off,
+    Messages,
+    Verbose,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum QuoteStyle {
+    Auto,
+    Single,
+    Double,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ImportModuleSpecifier {
+    Shortest,
+    ProjectRelative,
+    Relative,
+    NonRelative,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct SveltePluginSettings {
+    pub enable: Option<bool>,
+    pub diagnostics: Option<bool>,
+    pub hover: Option<bool>,
+    pub completions: Option<bool>,
+    pub code_actions: Option<bool>,
+    pub rename: Option<bool>,
+    /// Show a code lens telling whether a component is in runes or legacy mode. Svelte 5 only.
+    pub runes_legacy_mode_code_lens: Option<bool>,
+    /// The language assumed for `<script>` blocks without a `lang` attribute.
+    pub default_script_language: Option<ScriptLanguage>,
+    /// Severities of compiler warnings by code, like `a11y-no-onchange`.
+    pub compiler_warnings: BTreeMap<String, CompilerWarningSeverity>,
+    pub format: SvelteFormatSettings,
+    /// Options without a setting of their own, passed to the server as they are.
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct SvelteFormatSettings {
+    /// Whether the server formats Svelte files at all, see also `provide_formatter`.
+    pub enable: Option<bool>,
+    /// `prettier-plugin-svelte` options, used when the project has no Prettier config.
+    pub config: SvelteFormatConfig,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, rename_all(serialize = "camelCase"))]
+pub struct SvelteFormatConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub svelte_sort_order: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub svelte_strict_mode: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub svelte_bracket_new_line: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub svelte_allow_shorthand: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub svelte_indent_script_and_style: Option<bool>,
+    /// Set to `off` to format only the markup, leaving `<script>` and `<style>` contents alone.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub embedded_language_formatting: Option<EmbeddedLanguageFormatting>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EmbeddedLanguageFormatting {
+    Auto,
+    Off,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CompilerWarningSeverity {
+    Ignore,
+    Error,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScriptLanguage {
+    Js,
+    Ts,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct TypescriptPluginSettings {
+    pub enable: Option<bool>,
+    pub diagnostics: Option<bool>,
+    pub hover: Option<bool>,
+    pub completions: Option<bool>,
+    pub code_actions: Option<bool>,
+    pub rename: Option<bool>,
+    pub signature_help: Option<bool>,
+    /// Overrides the top-level `document_symbols` for this plugin.
+    pub document_symbols: Option<bool>,
+    /// Options without a setting of their own, passed to the server as they are.
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct HtmlPluginSettings {
+    pub enable: Option<bool>,
+    pub hover: Option<bool>,
+    pub completions: Option<bool>,
+    /// Whether completions include Emmet abbreviations.
+    pub emmet: Option<bool>,
+    pub tag_complete: Option<bool>,
+    pub linked_editing: Option<bool>,
+    /// Overrides the top-level `document_symbols` for this plugin.
+    pub document_symbols: Option<bool>,
+    /// HTML custom data describing extra tags and attributes.
+    pub custom_data: Vec<CustomData>,
+    /// Options without a setting of their own, passed to the server as they are.
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+/// A custom data file in the VS Code custom data format, or its contents.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum CustomData {
+    /// Path of a file, relative to the worktree.
+    Path(String),
+    Inline(Map<String, Value>),
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct CssPluginSettings {
+    pub enable: Option<bool>,
+    pub diagnostics: Option<bool>,
+    pub hover: Option<bool>,
+    pub completions: Option<bool>,
+    pub document_colors: Option<bool>,
+    pub color_presentations: Option<bool>,
+    /// Overrides the top-level `document_symbols` for this plugin.
+    pub document_symbols: Option<bool>,
+    /// CSS custom data describing extra properties, at-rules and pseudo-classes.
+    pub custom_data: Vec<CustomData>,
+    /// Severities of the CSS service's lint rules, using the same keys as VS Code's
+    /// `css.lint.*` settings.
+    pub lint: Map<String, Value>,
+    /// Options without a setting of their own, passed to the server as they are.
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+impl SvelteSettings {
+    pub fn for_worktree(id: &zed::LanguageServerId, worktree: &zed::Worktree) -> Result<Self> {
+        let settings = LspSettings::for_worktree(id.as_ref(), worktree)?.settings;
+
+        let mut settings = settings.map(Self::parse).unwrap_or_default();
+        // Everything the extension does starts with reading the settings, so this keeps the
+        // level current
+        log::set_level(settings.log_level.unwrap_or_default());
+
+        settings.project_keys = project::lsp_settings_keys(worktree, id.as_ref());
+        // Otherwise opening a repository could be all it takes to trust it
+        if settings.project_keys.contains("trust_workspace") {
+            warn!(
+                "Ignoring `trust_workspace` from the project's .zed/settings.json, it can only be \
+                 turned on in your user settings"
+            );
+            settings.trust_workspace = None;
+        }
+        Ok(settings)
+    }
+
+    /// Deserializes `settings`, ignoring any setting with an invalid value (with a warning), so
+    /// a typo in one setting doesn't keep the server from starting.
+    fn parse(mut value: Value) -> Self {
+        let mut settings: Self = match serde_json::from_value(value.clone()) {
+            Ok(settings) => settings,
+            Err(_) => {
+                match &mut value {
+                    Value::Object(value) => remove_invalid(value, &mut Vec::new()),
+                    _ => warn!("Ignoring the settings, they must be an object"),
+                }
+                serde_json::from_value(value).unwrap_or_default()
+            }
+        };
+
+        // A quote style is meant for the project's imports, so one set for TypeScript also
+        // applies to JavaScript unless that has its own
+        let javascript = &mut settings.javascript.preferences;
+        javascript.quote_style = javascript
+            .quote_style
+            .or(settings.typescript.preferences.quote_style);
+        settings
+    }
+
+    /// Returns whether `setting` can be used to run code, which settings the project sets
+    /// itself only can with `trust_workspace`.
+    pub fn trusted(&self, setting: &str) -> bool {
+        self.trust_workspace() || !self.project_keys.contains(setting)
+    }
+
+    pub fn prefer_local(&self) -> bool {
+        self.prefer_local.unwrap_or(true)
+    }
+
+    pub fn freeze(&self) -> bool {
+        self.freeze.unwrap_or(false)
+    }
+
+    pub fn notify_updates(&self) -> bool {
+        self.notify_updates.unwrap_or(true)
+    }
+
+    pub fn use_previous_version(&self) -> bool {
+        self.use_previous_version.unwrap_or(false)
+    }
+
+    pub fn always_install_ts_plugin(&self) -> bool {
+        self.always_install_ts_plugin.unwrap_or(false)
+    }
+
+    pub fn trust_workspace(&self) -> bool {
+        self.trust_workspace.unwrap_or(false)
+    }
+
+    pub fn dont_filter_incomplete_completions(&self) -> bool {
+        self.dont_filter_incomplete_completions.unwrap_or(true)
+    }
+
+    pub fn load_env_file(&self) -> bool {
+        self.load_env_file.unwrap_or(false)
+    }
+
+    pub fn env_file(&self) -> &str {
+        self.env_file.as_deref().unwrap_or(".env")
+    }
+
+    pub fn clean_up_installs(&self) -> bool {
+        self.clean_up_installs.unwrap_or(true)
+    }
+
+    pub fn import_vscode_settings(&self) -> bool {
+        self.import_vscode_settings.unwrap_or(false)
+    }
+
+    pub fn use_workspace_typescript(&self) -> bool {
+        self.use_workspace_typescript.unwrap_or(true)
+    }
+
+    pub fn enable_ts_plugin(&self) -> bool {
+        self.enable_ts_plugin.unwrap_or(true)
+    }
+
+    pub fn use_system_node(&self) -> bool {
+        self.use_system_node.unwrap_or(false)
+    }
+
+    pub fn provide_formatter(&self) -> bool {
+        self.provide_formatter.unwrap_or(true)
+    }
+
+    /// Returns the `emmet`, `prettier`, `scss` and `less` sections of the server's
+    /// configuration, for the ones the user has set. They're passed on with their keys as
+    /// they are, leaving out Prettier options that can't be valid.
+    pub fn service_configuration(&self) -> Map<String, Value> {
+        let prettier = self.prettier.as_ref().map(prettier_options);
+        [
+            ("emmet", self.emmet.as_ref()),
+            ("prettier", prettier.as_ref()),
+            ("scss", self.scss.as_ref()),
+            ("less", self.less.as_ref()),
+        ]
+        .into_iter()
+        .filter_map(|(section, options)| Some((section.to_string(), options?.clone().into())))
+        .collect()
+    }
+
+    /// Returns the `svelte.plugin` configuration for the toggles the user has set,
+    /// leaving everything else to the server defaults. `svelte_major` is the major version
+    /// of Svelte the project depends on, if known.
+    pub fn plugin_configuration(&self, svelte_major: Option<u64>) -> Option<Value> {
+        let mut plugin = Map::new();
+
+        let selection_range = self.selection_range.enable;
+        if let Some(svelte) = self.svelte.configuration(svelte_major, selection_range) {
+            plugin.insert("svelte".into(), svelte);
+        }
+        if let Some(html) = self.html.configuration(self.document_symbols) {
+            plugin.insert("html".into(), html);
+        }
+        if let Some(css) = self
+            .css
+            .configuration(self.document_symbols, selection_range)
+        {
+            plugin.insert("css".into(), css);
+        }
+        if let Some(typescript) = self.typescript_plugin.configuration(
+            self.semantic_tokens.enable,
+            self.document_symbols,
+            selection_range,
+        ) {
+            plugin.insert("typescript".into(), typescript);
+        }
+
+        (!plugin.is_empty()).then_some(Value::Object(plugin))
+    }
+}
+
+impl ScriptSettings {
+    /// Adds the preferences the user has set to the `typescript`/`javascript` section `config`.
+    pub fn apply(&self, config: &mut Value) {
+        if let Some(preferences) = self.preferences.configuration() {
+            merge(&mut config["preferences"], preferences);
+        }
+        if let Some(auto_imports) = self.suggest.auto_imports {
+            config["suggest"]["autoImports"] = auto_imports.into();
+        }
+        self.inlay_hints.apply(&mut config["inlayHints"]);
+    }
+}
+
+impl ScriptPreferences {
+    /// Returns the `preferences` the user has set, in the shape both the server and vtsls
+    /// read them.
+    pub fn configuration(&self) -> Option<Value> {
+        let mut preferences = Map::new();
+        if let Some(specifier) = self.import_module_specifier {
+            preferences.insert("importModuleSpecifier".into(), json!(specifier));
+        }
+        if let Some(quote_style) = self.quote_style {
+            preferences.insert("quoteStyle".into(), json!(quote_style));
+        }
+        (!preferences.is_empty()).then_some(Value::Object(preferences))
+    }
+}
+
+impl InlayHintSettings {
+    fn apply(&self, config: &mut Value) {
+        let parameter_names = &self.parameter_names;
+        if let Some(enabled) = parameter_names.enabled {
+            config["parameterNames"]["enabled"] = json!(enabled);
+        }
+        if let Some(suppress) = parameter_names.suppress_when_argument_matches_name {
+            config["parameterNames"]["suppressWhenArgumentMatchesName"] = suppress.into();
+        }
+
+        let variable_types = &self.variable_types;
+        if let Some(enabled) = variable_types.enabled {
+            config["variableTypes"]["enabled"] = enabled.into();
+        }
+        if let Some(suppress) = variable_types.suppress_when_type_matches_name {
+            config["variableTypes"]["suppressWhenTypeMatchesName"] = suppress.into();
+        }
+
+        for (kind, toggle) in [
+            ("parameterTypes", &self.parameter_types),
+            ("propertyDeclarationTypes", &self.property_declaration_types),
+            ("functionLikeReturnTypes", &self.function_like_return_types),
+            ("enumMemberValues", &self.enum_member_values),
+        ] {
+            if let Some(enabled) = toggle.enabled {
+                config[kind]["enabled"] = enabled.into();
+            }
+        }
+    }
+}
+
+impl SveltePluginSettings {
+    fn configuration(
+        &self,
+        svelte_major: Option<u64>,
+        selection_range: Option<bool>,
+    ) -> Option<Value> {
+        // Runes mode only exists since Svelte 5, so leave it out for projects on older versions
+        let runes_legacy_mode_code_lens = self
+            .runes_legacy_mode_code_lens
+            .filter(|_| svelte_major.is_none_or(|major| major >= 5));
+
+        let mut plugin = plugin_configuration(
+            self.enable,
+            &[
+                ("diagnostics", self.diagnostics),
+                ("hover", self.hover),
+                ("completions", self.completions),
+                ("codeActions", self.code_actions),
+                ("rename", self.rename),
+                ("runesLegacyModeCodeLens", runes_legacy_mode_code_lens),
+                ("selectionRange", selection_range),
+            ],
+            &self.other,
+        );
+
+        if self.enable == Some(false) {
+            return plugin;
+        }
+        // Unlike the toggles, these are plain values
+        if let Some(language) = self.default_script_language {
+            plugin.get_or_insert_with(|| json!({}))["defaultScriptLanguage"] = json!(language);
+        }
+        if !self.compiler_warnings.is_empty() {
+            plugin.get_or_insert_with(|| json!({}))["compilerWarnings"] =
+                json!(self.compiler_warnings);
+        }
+        if let Some(format) = self.format.configuration() {
+            merge(
+                plugin.get_or_insert_with(|| json!({})),
+                json!({ "format": format }),
+            );
+        }
+        plugin
+    }
+}
+
+impl SvelteFormatSettings {
+    fn configuration(&self) -> Option<Value> {
+        let mut format = Map::new();
+        if let Some(enable) = self.enable {
+            format.insert("enable".into(), enable.into());
+        }
+        if self.config != SvelteFormatConfig::default() {
+            format.insert("config".into(), json!(self.config));
+        }
+        (!format.is_empty()).then_some(Value::Object(format))
+    }
+}
+
+impl TypescriptPluginSettings {
+    fn configuration(
+        &self,
+        semantic_tokens: Option<bool>,
+        document_symbols: Option<bool>,
+        selection_range: Option<bool>,
+    ) -> Option<Value> {
+        plugin_configuration(
+            self.enable,
+            &[
+                ("diagnostics", self.diagnostics),
+                ("hover", self.hover),
+                ("completions", self.completions),
+                ("codeActions", self.code_actions),
+                ("rename", self.rename),
+                ("signatureHelp", self.signature_help),
+                ("semanticTokens", semantic_tokens),
+                (
+                    "documentSymbols",
+                    self.document_symbols.or(document_symbols),
+                ),
+                ("selectionRange", selection_range),
+            ],
+            &self.other,
+        )
+    }
+}
+
+impl HtmlPluginSettings {
+    fn configuration(&self, document_symbols: Option<bool>) -> Option<Value> {
+        let mut plugin = plugin_configuration(
+            self.enable,
+            &[
+                ("hover", self.hover),
+                ("completions", self.completions),
+                ("tagComplete", self.tag_complete),
+                ("linkedEditing", self.linked_editing),
+                (
+                    "documentSymbols",
+                    self.document_symbols.or(document_symbols),
+                ),
+            ],
+            &self.other,
+        );
+
+        if self.enable == Some(false) {
+            return plugin;
+        }
+        // The server reads it next to the completions toggle
+        if let Some(emmet) = self.emmet {
+            plugin.get_or_insert_with(|| json!({}))["completions"]["emmet"] = emmet.into();
+        }
+        plugin
+    }
+}
+
+impl CssPluginSettings {
+    fn configuration(
+        &self,
+        document_symbols: Option<bool>,
+        selection_range: Option<bool>,
+    ) -> Option<Value> {
+        plugin_configuration(
+            self.enable,
+            &[
+                ("diagnostics", self.diagnostics),
+                ("hover", self.hover),
+                ("completions", self.completions),
+                ("documentColors", self.document_colors),
+                ("colorPresentations", self.color_presentations),
+                (
+                    "documentSymbols",
+                    self.document_symbols.or(document_symbols),
+                ),
+                ("selectionRange", selection_range),
+            ],
+            &self.other,
+        )
+    }
+}
+
+fn plugin_configuration(
+    enable: Option<bool>,
+    features: &[(&str, Option<bool>)],
+    other: &Map<String, Value>,
+) -> Option<Value> {
+    // A disabled plugin turns off all of its features, so there's no point in sending them
+    if enable == Some(false) {
+        return Some(json!({ "enable": false }));
+    }
+
+    let mut plugin = Map::new();
+    if let Some(enable) = enable {
+        plugin.insert("enable".into(), enable.into());
+    }
+    for (feature, enable) in features {
+        if let Some(enable) = enable {
+            plugin.insert((*feature).into(), json!({ "enable": enable }));
+        }
+    }
+
+    let mut plugin = Value::Object(plugin);
+    merge(&mut plugin, Value::Object(other.clone()));
+    (plugin != json!({})).then_some(plugin)
+}
+
+/// Returns the options of `prettier` that can be valid, warning about the others, as Prettier
+/// would otherwise reject the whole config. Options are strings, numbers, booleans or lists,
+/// and each of the `overrides` names its `files` and has an object of `options`.
+fn prettier_options(prettier: &Map<String, Value>) -> Map<String, Value> {
+    let mut options = Map::new();
+    for (key, value) in prettier {
+        let problem = match (key.as_str(), value) {
+            ("overrides", Value::Array(overrides)) => overrides
+                .iter()
+                .any(|rule| {
+                    !(rule["files"].is_string() || rule["files"].is_array())
+                        || !rule["options"].is_object()
+                })
+                .then_some("each override needs `files` and an object of `options`"),
+            (_, Value::Object(_) | Value::Null) => {
+                Some("Prettier options are strings, numbers, booleans or lists")
+            }
+            _ => None,
+        };
+        match problem {
+            Some(problem) => warn!("Ignoring invalid setting `prettier.{key}`: {problem}"),
+            None => {
+                options.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    options
+}
+
+/// Merges `overrides` into `base`, descending into objects present in both so that sibling
+/// keys of `base` survive. A `null` in an object of `overrides` removes the key from `base`,
+/// and any other value, arrays included, replaces the one in `base`.
+pub fn merge(base: &mut Value, overrides: Value) {
+    match (base, overrides) {
+        (Value::Object(base), Value::Object(overrides)) => {
+            for (key, value) in overrides {
+                if value.is_null() {
+                    base.remove(&key);
+                    continue;
+                }
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
+
+/// Removes the settings in `settings`, found at `path`, that fail to deserialize on their own.
+/// Objects are looked into first, so only their invalid settings are removed.
+fn remove_invalid(settings: &mut Map<String, Value>, path: &mut Vec<String>) {
+    let keys: Vec<String> = settings.keys().cloned().collect();
+    for key in keys {
+        path.push(key.clone());
+        if check(path, &settings[&key]).is_err() {
+            if let Value::Object(nested) = &mut settings[&key] {
+                remove_invalid(nested, path);
+            }
+            if let Err(error) = check(path, &settings[&key]) {
+                warn!("Ignoring invalid setting `{}`: {error}", path.join("."));
+                settings.remove(&key);
+            }
+        }
+        path.pop();
+    }
+}
+
+/// Deserializes the settings with only `value` set at `path`.
+fn check(path: &[String], value: &Value) -> serde_json::Result<()> {
+    let settings = path
+        .iter()
+        .rev()
+        .fold(value.clone(), |value, key| json!({ key: value }));
+    serde_json::from_value::<SvelteSettings>(settings).map(drop)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_keeps_sibling_keys_of_nested_objects() {
+        let mut base = json!({
+            "typescript": {
+                "inlayHints": {
+                    "parameterNames": { "enabled": "all", "suppressWhenArgumentMatchesName": true },
+                    "parameterTypes": { "enabled": true }
+                }
+            }
+        });
+        merge(
+            &mut base,
+            json!({ "typescript": { "inlayHints": { "parameterNames": { "enabled": "none" } } } }),
+        );
+        assert_eq!(
+            base,
+            json!({
+                "typescript": {
+                    "inlayHints": {
+                        "parameterNames": { "enabled": "none", "suppressWhenArgumentMatchesName": true },
+                        "parameterTypes": { "enabled": true }
+                    }
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn merge_adds_keys_missing_from_the_base() {
+        let mut base = json!({ "provideFormatter": true });
+        merge(
+            &mut base,
+            json!({ "configuration": { "svelte": { "plugin": {} } } }),
+        );
+        assert_eq!(
+            base,
+            json!({ "provideFormatter": true, "configuration": { "svelte": { "plugin": {} } } })
+        );
+    }
+
+    #[test]
+    fn merge_removes_keys_set_to_null() {
+        let mut base =
+            json!({ "typescript": { "inlayHints": { "enabled": true }, "tsdk": "lib" } });
+        merge(&mut base, json!({ "typescript": { "inlayHints": null } }));
+        assert_eq!(base, json!({ "typescript": { "tsdk": "lib" } }));
+
+        merge(&mut base, json!({ "javascript": null }));
+        assert_eq!(base, json!({ "typescript": { "tsdk": "lib" } }));
+    }
+
+    #[test]
+    fn merge_replaces_arrays_and_scalars() {
+        let mut base = json!({ "customData": ["a.json", "b.json"], "enable": true });
+        merge(
+            &mut base,
+            json!({ "customData": ["c.json"], "enable": false }),
+        );
+        assert_eq!(base, json!({ "customData": ["c.json"], "enable": false }));
+    }
+
+    #[test]
+    fn merge_replaces_values_of_another_type() {
+        let mut base = json!({ "lint": { "unknownAtRules": "ignore" }, "enable": true });
+        merge(
+            &mut base,
+            json!({ "lint": "off", "enable": { "hover": false } }),
+        );
+        assert_eq!(base, json!({ "lint": "off", "enable": { "hover": false } }));
+    }
+
+    fn settings(settings: Value) -> SvelteSettings {
+        SvelteSettings::parse(settings)
+    }
+
+    #[test]
+    fn plugin_configuration_leaves_unset_toggles_to_the_server() {
+        assert_eq!(SvelteSettings::default().plugin_configuration(None), None);
+    }
+
+    #[test]
+    fn plugin_configuration_nests_feature_toggles() {
+        let settings = settings(json!({
+            "html": { "hover": false, "tag_complete": true },
+            "css": { "diagnostics": false }
+        }));
+        assert_eq!(
+            settings.plugin_configuration(None),
+            Some(json!({
+                "html": { "hover": { "enable": false }, "tagComplete": { "enable": true } },
+                "css": { "diagnostics": { "enable": false } }
+            }))
+        );
+    }
+
+    #[test]
+    fn disabled_plugin_sends_only_its_enable() {
+        let settings = settings(json!({
+            "html": { "enable": false, "hover": true, "emmet": false },
+            "css": { "enable": true, "completions": false }
+        }));
+        assert_eq!(
+            settings.plugin_configuration(None),
+            Some(json!({
+                "html": { "enable": false },
+                "css": { "enable": true, "completions": { "enable": false } }
+            }))
+        );
+    }
+
+    #[test]
+    fn plugin_configuration_passes_other_options_through() {
+        let settings = settings(json!({
+            "css": { "hover": false, "globals": "./src/global.css" }
+        }));
+        assert_eq!(
+            plugin_configuration(
+                settings.css.enable,
+                &[("hover", settings.css.hover)],
+                &settings.css.other
+            ),
+            Some(json!({ "hover": { "enable": false }, "globals": "./src/global.css" }))
+        );
+    }
+
+    #[test]
+    fn default_script_language_goes_in_the_svelte_plugin() {
+        let settings = settings(json!({ "svelte": { "default_script_language": "ts" } }));
+        let plugin = settings.plugin_configuration(None).unwrap();
+        assert_eq!(plugin["svelte"]["defaultScriptLanguage"], json!("ts"));
+    }
+
+    #[test]
+    fn quote_style_for_typescript_also_applies_to_javascript() {
+        let settings = settings(json!({
+            "typescript": { "preferences": { "quote_style": "single" } }
+        }));
+        let mut typescript = json!({});
+        settings.typescript.apply(&mut typescript);
+        let mut javascript = json!({});
+        settings.javascript.apply(&mut javascript);
+
+        assert_eq!(typescript["preferences"], json!({ "quoteStyle": "single" }));
+        assert_eq!(javascript["preferences"], json!({ "quoteStyle": "single" }));
+    }
+
+    #[test]
+    fn quote_style_goes_in_the_preferences_of_each_language() {
+        let settings = settings(json!({
+            "typescript": { "preferences": { "quote_style": "single" } },
+            "javascript": { "preferences": { "quote_style": "double" } }
+        }));
+        let mut typescript = json!({});
+        settings.typescript.apply(&mut typescript);
+        let mut javascript = json!({});
+        settings.javascript.apply(&mut javascript);
+
+        assert_eq!(typescript["preferences"], json!({ "quoteStyle": "single" }));
+        assert_eq!(javascript["preferences"], json!({ "quoteStyle": "double" }));
+    }
+
+    #[test]
+    fn typescript_and_javascript_are_configured_apart() {
+        let settings = settings(json!({
+            "javascript": {
+                "inlay_hints": {
+                    "parameter_names": { "enabled": "none" },
+                    "variable_types": { "enabled": false }
+                }
+            }
+        }));
+        let defaults = json!({
+            "inlayHints": {
+                "parameterNames": { "enabled": "all" },
+                "variableTypes": { "enabled": true }
+            }
+        });
+        let mut typescript = defaults.clone();
+        settings.typescript.apply(&mut typescript);
+        let mut javascript = defaults.clone();
+        settings.javascript.apply(&mut javascript);
+
+        assert_eq!(typescript, defaults);
+        assert_eq!(
+            javascript,
+            json!({
+                "inlayHints": {
+                    "parameterNames": { "enabled": "none" },
+                    "variableTypes": { "enabled": false }
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn parse_ignores_only_the_invalid_settings() {
+        let settings = SvelteSettings::parse(json!({
+            "max_memory_mb": "4096",
+            "trace": "loud",
+            "use_system_node": true,
+        }));
+        assert_eq!(settings.max_memory_mb, None);
+        assert_eq!(settings.trace, None);
+        assert_eq!(settings.use_system_node, Some(true));
+    }
+
+    #[test]
+    fn parse_ignores_invalid_nested_settings() {
+        let settings = SvelteSettings::parse(json!({
+            "svelte": {
+                "compiler_warnings": { "a11y-autofocus": "ignore", "css-unused-selector": "ignroe" },
+                "hover": false,
+            },
+            "typescript": { "preferences": { "quote_style": "backtick", "import_module_specifier": "relative" } },
+        }));
+        assert_eq!(
+            settings.svelte.compiler_warnings,
+            BTreeMap::from([(
+                "a11y-autofocus".to_string(),
+                CompilerWarningSeverity::Ignore
+            )])
+        );
+        assert_eq!(settings.svelte.hover, Some(false));
+        assert_eq!(settings.typescript.preferences.quote_style, None);
+        assert_eq!(
+            settings.typescript.preferences.import_module_specifier,
+            Some(ImportModuleSpecifier::Relative)
+        );
+    }
+
+    #[test]
+    fn parse_falls_back_to_the_defaults() {
+        assert_eq!(
+            SvelteSettings::parse(json!("svelte")),
+            SvelteSettings::default()
+        );
+        assert_eq!(
+            SvelteSettings::parse(json!({ "wrapper": "direnv exec ." })),
+            SvelteSettings::default()
+        );
+    }
+
+    #[test]
+    fn service_configuration_forwards_scss_and_less_options() {
+        let settings = settings(json!({
+            "scss": { "lint": { "unknownAtRules": "ignore" }, "completion": { "triggerPropertyValueCompletion": false } },
+            "less": { "validate": false },
+        }));
+        let configuration = Value::Object(settings.service_configuration());
+        assert_eq!(
+            configuration,
+            json!({
+                "scss": { "lint": { "unknownAtRules": "ignore" }, "completion": { "triggerPropertyValueCompletion": false } },
+                "less": { "validate": false },
+            })
+        );
+    }
+
+    #[test]
+    fn service_configuration_leaves_out_unset_sections() {
+        assert_eq!(
+            SvelteSettings::default().service_configuration(),
+            Map::new()
+        );
+    }
+
+    #[test]
+    fn service_configuration_forwards_prettier_options() {
+        let settings = settings(json!({
+            "prettier": {
+                "tabWidth": 4,
+                "semi": false,
+                "plugins": ["prettier-plugin-tailwindcss"],
+                "overrides": [{ "files": "*.svelte", "options": { "useTabs": true } }],
+            }
+        }));
+        assert_eq!(
+            settings.service_configuration()["prettier"],
+            json!({
+                "tabWidth": 4,
+                "semi": false,
+                "plugins": ["prettier-plugin-tailwindcss"],
+                "overrides": [{ "files": "*.svelte", "options": { "useTabs": true } }],
+            })
+        );
+    }
+
+    #[test]
+    fn service_configuration_leaves_out_invalid_prettier_options() {
+        let settings = settings(json!({
+            "prettier": {
+                "tabWidth": 2,
+                "script": { "semi": false },
+                "overrides": [{ "files": "*.ts", "options": "semi" }],
+            }
+        }));
+        assert_eq!(
+            settings.service_configuration()["prettier"],
+            json!({ "tabWidth": 2 })
+        );
+        assert_eq!(
+            SvelteSettings::parse(json!({ "prettier": true })).prettier,
+            None
+        );
+    }
+}
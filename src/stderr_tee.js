@@ -0,0 +1,24 @@
+// Preloaded with `node --require` to copy the server's stderr into a log file.
+// The file is truncated on every start so it doesn't grow without bounds.
+const fs = require("fs");
+
+const file = process.env.ZED_SVELTE_LOG_FILE;
+let fd;
+try {
+  fd = fs.openSync(file, "w");
+} catch (error) {
+  process.stderr.write(`Failed to open the log file ${file}: ${error.message}\n`);
+}
+
+if (fd !== undefined) {
+  const write = process.stderr.write.bind(process.stderr);
+
+  process.stderr.write = (chunk, ...args) => {
+    try {
+      fs.writeSync(fd, chunk);
+    } catch {
+      // Losing the copy, e.g. on a full disk, shouldn't take the server down
+    }
+    return write(chunk, ...args);
+  };
+}
@@ -1,32 +1,647 @@
-use std::{collections::HashSet, env, path::PathBuf};
-use zed_extension_api::{self as zed, serde_json, Result};
+mod dotenv;
+mod launches;
+mod links;
+mod log;
+mod project;
+mod prune;
+mod runtime;
+mod settings;
+mod version;
+mod vscode;
+
+use launches::Launches;
+use log::{debug, error, info, warn};
+use runtime::LaunchOptions;
+use settings::{CustomData, SvelteSettings, Trace};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    env, fs, io,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+use zed_extension_api::{self as zed, serde_json, settings::LspSettings, Result};
 
 struct SvelteExtension {
-    installed: HashSet<String>,
+    /// The extension's work directory, which is where Zed installs npm packages. Empty until
+    /// [`SvelteExtension::resolve_work_dir`] resolves it.
+    work_dir: PathBuf,
+    /// Versions of the packages checked during this session, keyed by worktree root and
+    /// package name.
+    installed: HashMap<(String, String), String>,
+    /// The last install error of each package, cleared once it installs successfully.
+    install_errors: HashMap<String, String>,
+    /// Recent server launches, keyed by worktree root.
+    launches: HashMap<String, Launches>,
+    /// Custom data files that couldn't be read and were already warned about.
+    custom_data_warnings: HashSet<String>,
 }
 
 const PACKAGE_NAME: &str = "svelte-language-server";
 const TS_PLUGIN_PACKAGE_NAME: &str = "typescript-svelte-plugin";
+const SERVER_PATH: &str = "bin/server.js";
+const CHANGELOG_URL: &str = "https://github.com/sveltejs/language-tools/releases";
+/// Where the install replaced by the last server update is kept.
+const PREVIOUS_DIR: &str = "previous";
+/// npm's lockfile, which is kept with the previous install so it matches its `node_modules`.
+const LOCKFILE_PATH: &str = "package-lock.json";
+/// Records the `file:` spec the server was last installed from.
+const TARBALL_SPEC_PATH: &str = "server-tarball";
+const SERVER_PATH_ENV_VAR: &str = "ZED_SVELTE_LS_PATH";
+const TS_PLUGIN_PATH_ENV_VAR: &str = "ZED_SVELTE_TS_PLUGIN_PATH";
+const LOG_FILE_ENV_VAR: &str = "ZED_SVELTE_LOG_FILE";
+const STDERR_TEE_PATH: &str = "stderr-tee.js";
+const CWD_ENV_VAR: &str = "ZED_SVELTE_CWD";
+const NODE_PATH_ENV_VAR: &str = "NODE_PATH";
+const CHDIR_PATH: &str = "chdir.js";
+
+/// Returns the directory of the worktree's own `svelte-language-server`, if it has one.
+fn local_server_package(worktree: &zed::Worktree) -> Option<PathBuf> {
+    let relative_path = format!("node_modules/{PACKAGE_NAME}");
+    worktree
+        .read_text_file(&format!("{relative_path}/package.json"))
+        .ok()?;
+    Some(PathBuf::from(worktree.root_path()).join(relative_path))
+}
+
+/// Warns when the project-local and extension-managed servers are different versions, as
+/// which one runs then decides which features work.
+fn warn_if_shadowed(worktree: &zed::Worktree, using_local: bool, reason: &str) {
+    let Some(local_version) = project::installed_version(worktree, PACKAGE_NAME) else {
+        return;
+    };
+    let Ok(Some(managed_version)) = zed::npm_package_installed_version(PACKAGE_NAME) else {
+        return;
+    };
+    if local_version == managed_version {
+        return;
+    }
+
+    let used = if using_local {
+        "project-local"
+    } else {
+        "extension-managed"
+    };
+    warn!(
+        "Found project-local {PACKAGE_NAME}@{local_version} and extension-managed \
+         {PACKAGE_NAME}@{managed_version}, running the {used} one as {reason}"
+    );
+}
+
+/// Returns the `engines.node` range of the package whose server entrypoint is `server_path`,
+/// which is the closest directory above it with a `package.json`.
+///
+/// Only packages inside the extension's work directory can be read, so this is `None` for
+/// project-local servers and overrides.
+fn engines_node(server_path: &Path) -> Option<String> {
+    let package_json = server_path.ancestors().skip(1).find_map(package_json)?;
+    package_json["engines"]["node"]
+        .as_str()
+        .map(ToString::to_string)
+}
+
+/// Reads the `package.json` of the package in `package_dir`, which must be inside the
+/// extension's work directory.
+fn package_json(package_dir: &Path) -> Option<serde_json::Value> {
+    let package_json = fs::read_to_string(package_dir.join("package.json")).ok()?;
+    serde_json::from_str(&package_json).ok()
+}
+
+/// Returns whether the TypeScript plugin at `plugin_path` supports the worktree's own
+/// TypeScript, going by the plugin's `peerDependencies`. Anything that can't be told is
+/// assumed to be compatible.
+fn ts_plugin_compatible(worktree: &zed::Worktree, plugin_path: &Path) -> bool {
+    let Some(typescript_version) = project::installed_version(worktree, "typescript") else {
+        return true;
+    };
+    let Some(package_json) = package_json(plugin_path) else {
+        return true;
+    };
+    let Some(range) = package_json["peerDependencies"]["typescript"].as_str() else {
+        return true;
+    };
+
+    if version::satisfies(&typescript_version, range) == Some(false) {
+        warn!(
+            "Not loading {TS_PLUGIN_PACKAGE_NAME}, it requires TypeScript {range} but the project \
+             has {typescript_version}"
+        );
+        return false;
+    }
+    true
+}
+
+/// Reads the file at `path`, or returns `None` if it's outside both the worktree and the work
+/// directory, which are the only places the extension can read from.
+fn read_file(worktree: &zed::Worktree, work_dir: &Path, path: &Path) -> Option<Result<String>> {
+    if let Ok(relative_path) = path.strip_prefix(worktree.root_path()) {
+        Some(worktree.read_text_file(&relative_path.to_string_lossy()))
+    } else if path.starts_with(work_dir) {
+        Some(fs::read_to_string(path).map_err(|e| e.to_string()))
+    } else {
+        None
+    }
+}
+
+/// Fails if the server entrypoint at `path` is missing, empty or not a script for Node.js,
+/// which would otherwise only show up as a server that never responds. `remedy` tells how
+/// to fix it.
+///
+/// Zed owns the server process, so this is the only check possible before it starts. Paths
+/// outside the worktree and the work directory can't be read and are let through.
+fn check_server_path(
+    worktree: &zed::Worktree,
+    work_dir: &Path,
+    path: &Path,
+    remedy: &str,
+) -> Result<()> {
+    let Some(contents) = read_file(worktree, work_dir, path) else {
+        return Ok(());
+    };
+
+    match entrypoint_problem(path, contents) {
+        Some(problem) => Err(format!("{problem}, {remedy}")),
+        None => Ok(()),
+    }
+}
+
+/// Returns what keeps the server entrypoint at `path`, with `contents`, from running with
+/// Node.js, if anything.
+fn entrypoint_problem(path: &Path, contents: Result<String>) -> Option<String> {
+    let contents = match contents {
+        Ok(contents) => contents,
+        Err(error) => {
+            return Some(format!(
+                "failed to read the server entrypoint {}: {error}",
+                path.display()
+            ))
+        }
+    };
+    if contents.trim().is_empty() {
+        return Some(format!("the server entrypoint {} is empty", path.display()));
+    }
+
+    // Like a `#!/bin/sh` launcher script, which the runtime would try to run as JavaScript
+    let interpreter = contents.lines().next()?.strip_prefix("#!")?.trim();
+    (!interpreter.contains("node") && !interpreter.contains("bun")).then(|| {
+        format!(
+            "the server entrypoint {} is a script for {interpreter}, not for Node.js",
+            path.display()
+        )
+    })
+}
+
+/// Returns the path of the executable a package's `package.json` names in `bin`, which is
+/// either the path of the package's only executable or maps names to paths.
+fn package_bin(package_json: &serde_json::Value) -> Option<&str> {
+    match &package_json["bin"] {
+        serde_json::Value::Object(bins) => bins.values().find_map(|bin| bin.as_str()),
+        bin => bin.as_str(),
+    }
+}
+
+/// Returns the modules directories to look for packages in, with where each came from:
+/// `modules_dir`, then each entry of `node_path` split at `separator`. Relative directories
+/// resolve against `root_path`.
+fn modules_dirs(
+    root_path: &Path,
+    modules_dir: Option<&str>,
+    node_path: Option<&str>,
+    separator: char,
+) -> Vec<(&'static str, PathBuf)> {
+    let modules_dir = modules_dir.map(|dir| ("modules_dir", root_path.join(dir)));
+    let node_path = node_path
+        .into_iter()
+        .flat_map(|node_path| node_path.split(separator))
+        .filter(|dir| !dir.trim().is_empty())
+        .map(|dir| (NODE_PATH_ENV_VAR, root_path.join(dir)));
+    modules_dir.into_iter().chain(node_path).collect()
+}
+
+/// Copies the install in `work_dir`, its `node_modules` and npm's lockfile, to `previous_dir`.
+///
+/// The whole tree is kept, as the update and pruning afterwards remove the dependencies
+/// only the previous version needed. It's copied rather than moved, so the TypeScript plugin
+/// a running vtsls has loaded stays where it is.
+fn retain_install(work_dir: &Path, previous_dir: &Path) -> io::Result<()> {
+    if previous_dir.exists() {
+        fs::remove_dir_all(previous_dir)?;
+    }
+    copy_dir(
+        &work_dir.join("node_modules"),
+        &previous_dir.join("node_modules"),
+    )?;
+    let lockfile = work_dir.join(LOCKFILE_PATH);
+    if lockfile.exists() {
+        fs::copy(lockfile, previous_dir.join(LOCKFILE_PATH))?;
+    }
+    Ok(())
+}
+
+/// Puts the install kept in `previous_dir` back in place of the one in `work_dir`.
+fn restore_install(work_dir: &Path, previous_dir: &Path) -> io::Result<()> {
+    let node_modules = work_dir.join("node_modules");
+    if node_modules.exists() {
+        fs::remove_dir_all(&node_modules)?;
+    }
+    fs::rename(previous_dir.join("node_modules"), node_modules)?;
+    let lockfile = previous_dir.join(LOCKFILE_PATH);
+    if lockfile.exists() {
+        fs::rename(lockfile, work_dir.join(LOCKFILE_PATH))?;
+    }
+    Ok(())
+}
+
+/// Copies the directory `from` to `to`, skipping symlinks. npm only uses those for the
+/// executables in `.bin`, which the server doesn't need.
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let to = to.join(entry.file_name());
+        if file_type.is_dir() {
+            copy_dir(&entry.path(), &to)?;
+        } else if file_type.is_file() {
+            fs::copy(entry.path(), to)?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns whether to ask the registry for a version newer than `installed_version`. If the
+/// package was already checked in this session we won't reinstall it, unless the version on
+/// disk changed underneath us, and a frozen install is never updated.
+fn needs_update_check(
+    settings: &SvelteSettings,
+    installed_version: &str,
+    checked_version: Option<&str>,
+) -> bool {
+    checked_version != Some(installed_version) && !settings.freeze()
+}
+
+/// Returns the `lib` directory of the TypeScript the server should use instead of its own,
+/// with its version if known: `tsdk` if set, otherwise the worktree's own TypeScript.
+fn tsdk(worktree: &zed::Worktree, settings: &SvelteSettings) -> Option<(PathBuf, Option<String>)> {
+    tsdk_location(&worktree.root_path(), settings, || {
+        project::installed_version(worktree, "typescript")
+    })
+}
+
+/// Like [`tsdk`], with `workspace_version` telling the version of the worktree's own
+/// TypeScript, if it has one. TypeScript from the worktree is only used with
+/// `trust_workspace`, as the server runs it.
+fn tsdk_location(
+    root_path: &str,
+    settings: &SvelteSettings,
+    workspace_version: impl FnOnce() -> Option<String>,
+) -> Option<(PathBuf, Option<String>)> {
+    let root_path_buf = PathBuf::from(root_path);
+    if let Some(tsdk) = &settings.tsdk {
+        let path = root_path_buf.join(tsdk);
+        return trusted(root_path, settings, "tsdk", Some(&path)).then_some((path, None));
+    }
+    if !settings.use_workspace_typescript() {
+        return None;
+    }
+
+    let version = workspace_version()?;
+    let path = root_path_buf.join("node_modules/typescript/lib");
+    trusted(root_path, settings, "use_workspace_typescript", Some(&path))
+        .then_some((path, Some(version)))
+}
+
+/// Returns whether code from `path`, or from `setting` in general, may run.
+///
+/// Without `trust_workspace`, settings the project sets itself and paths inside the worktree
+/// are skipped, logging what would have run.
+fn trusted(root_path: &str, settings: &SvelteSettings, setting: &str, path: Option<&Path>) -> bool {
+    let reason = if !settings.trusted(setting) {
+        "it's set by the project's .zed/settings.json"
+    } else if !settings.trust_workspace() && path.is_some_and(|path| path.starts_with(root_path)) {
+        "it's inside the project"
+    } else {
+        return true;
+    };
+
+    match path {
+        Some(path) => info!(
+            "Not using {} from {setting} because {reason} and `trust_workspace` is off for this project",
+            path.display()
+        ),
+        None => info!(
+            "Ignoring {setting} because {reason} and `trust_workspace` is off for this project"
+        ),
+    }
+    false
+}
+
+/// Returns the variables to set for the server: those of the `.env` file with `env_file`
+/// contents, overridden by `env`. Both override the environment the server inherits.
+///
+/// An `env` the project sets needs `trust_workspace`, as variables like `NODE_OPTIONS` can
+/// make Node.js run any code.
+fn user_env(
+    root_path: &str,
+    settings: &SvelteSettings,
+    env_file: Option<&str>,
+) -> BTreeMap<String, String> {
+    let mut env = BTreeMap::new();
+    if let Some(contents) = env_file {
+        let variables = dotenv::parse(contents);
+        info!(
+            "Loaded {} variables from {} into the server's environment",
+            variables.len(),
+            settings.env_file()
+        );
+        env.extend(variables);
+    }
+    if !settings.env.is_empty() && trusted(root_path, settings, "env", None) {
+        env.extend(settings.env.clone());
+    }
+    env
+}
+
+/// Returns the path the user pointed the extension at, either through the setting `name`
+/// or through `env_var` in the worktree's shell environment. Relative paths resolve against
+/// the worktree.
+fn path_override(
+    worktree: &zed::Worktree,
+    settings: &SvelteSettings,
+    name: &str,
+    setting: Option<&str>,
+    env_var: &str,
+) -> Result<Option<PathBuf>> {
+    let (source, path) = match setting {
+        Some(path) => (name, path.to_string()),
+        None => match worktree
+            .shell_env()
+            .into_iter()
+            .find(|(key, _)| key == env_var)
+        {
+            Some((_, path)) => (env_var, path),
+            None => return Ok(None),
+        },
+    };
+
+    if path.trim().is_empty() {
+        return Err(format!("the path override from {source} is empty"));
+    }
+
+    let path = PathBuf::from(worktree.root_path()).join(path);
+    if !trusted(&worktree.root_path(), settings, source, Some(&path)) {
+        return Ok(None);
+    }
+    info!(
+        "Overriding the installed package with {} from {source}",
+        path.display()
+    );
+    Ok(Some(path))
+}
+
+/// Collects the custom data of `entries`, reading the files from the worktree. Files that
+/// can't be read are skipped, and each is warned about once per session.
+fn custom_data(
+    worktree: &zed::Worktree,
+    entries: &[CustomData],
+    warnings: &mut HashSet<String>,
+) -> Vec<serde_json::Value> {
+    let mut custom_data = Vec::new();
+    for entry in entries {
+        let path = match entry {
+            CustomData::Path(path) => path,
+            CustomData::Inline(data) => {
+                custom_data.push(data.clone().into());
+                continue;
+            }
+        };
+
+        let data = worktree
+            .read_text_file(path)
+            .and_then(|data| serde_json::from_str(&data).map_err(|e| e.to_string()));
+        match data {
+            Ok(data) => {
+                warnings.remove(path);
+                custom_data.push(data);
+            }
+            Err(error) => {
+                if warnings.insert(path.clone()) {
+                    warn!("Skipping custom data {path}, it can't be read: {error}");
+                }
+            }
+        }
+    }
+    custom_data
+}
+
+/// Builds the configuration the server reads its `svelte`, `typescript`, `javascript`,
+/// `emmet`, `prettier`, `html`, `css`, `scss` and `less` sections from, both at initialization
+/// and on every configuration change. `ts_plugin_available` tells whether vtsls gets the
+/// TypeScript plugin.
+fn configuration(
+    settings: &SvelteSettings,
+    worktree: &zed::Worktree,
+    ts_plugin_available: bool,
+    custom_data_warnings: &mut HashSet<String>,
+) -> serde_json::Value {
+    let config = serde_json::json!({
+      "inlayHints": {
+        "parameterNames": {
+          "enabled": "all",
+          "suppressWhenArgumentMatchesName": true
+        },
+        "parameterTypes": {
+          "enabled": true
+        },
+        "variableTypes": {
+          "enabled": true,
+          "suppressWhenTypeMatchesName": true
+        },
+        "propertyDeclarationTypes": {
+          "enabled": true
+        },
+        "functionLikeReturnTypes": {
+          "enabled": true
+        },
+        "enumMemberValues": {
+          "enabled": true
+        }
+      }
+    });
+
+    let mut typescript = config.clone();
+    settings.typescript.apply(&mut typescript);
+    let mut javascript = config;
+    settings.javascript.apply(&mut javascript);
+
+    let mut configuration = serde_json::json!({
+        "typescript": typescript,
+        "javascript": javascript
+    });
+    let svelte_major = project::svelte_major_version(worktree);
+    if let Some(plugin) = settings.plugin_configuration(svelte_major) {
+        configuration["svelte"]["plugin"] = plugin;
+    }
+    // Tell the server the TypeScript plugin is there, like VS Code does, so they split the
+    // work between them instead of both doing it
+    configuration["svelte"]["enable-ts-plugin"] =
+        (settings.enable_ts_plugin() && ts_plugin_available).into();
+
+    if settings.trace.unwrap_or_default() == Trace::Verbose {
+        configuration["svelte"]["language-server"] = serde_json::json!({ "debug": true });
+    }
+
+    // Settings carried over from VS Code only fill in what the Zed settings leave unset
+    if settings.import_vscode_settings() {
+        if let Some(mut vscode_configuration) = vscode::svelte_settings(worktree) {
+            settings::merge(&mut vscode_configuration, configuration);
+            configuration = vscode_configuration;
+        }
+    }
+    for (section, options) in settings.service_configuration() {
+        configuration[section] = options;
+    }
+    if !settings.html.custom_data.is_empty() {
+        configuration["html"]["customData"] =
+            custom_data(worktree, &settings.html.custom_data, custom_data_warnings).into();
+    }
+    let mut lint = settings.css.lint.clone();
+    if !lint.contains_key("unknownAtRules") && project::uses_tailwind(worktree) {
+        lint.insert("unknownAtRules".into(), "ignore".into());
+    }
+    if !lint.is_empty() {
+        configuration["css"]["lint"] = lint.into();
+    }
+    if !settings.css.custom_data.is_empty() {
+        configuration["css"]["customData"] =
+            custom_data(worktree, &settings.css.custom_data, custom_data_warnings).into();
+    }
+
+    configuration
+}
+
+/// Returns the server's initialization options for `settings`, before the raw
+/// `initialization_options` are merged over them.
+fn initialization_options(
+    settings: &SvelteSettings,
+    configuration: serde_json::Value,
+    tsdk: Option<&Path>,
+) -> serde_json::Value {
+    let mut options = serde_json::json!({
+        "provideFormatter": settings.provide_formatter(),
+        "dontFilterIncompleteCompletions": settings.dont_filter_incomplete_completions(),
+        // Otherwise the server loads the project's `svelte.config.js`, running its code
+        "isTrusted": settings.trust_workspace(),
+        "configuration": configuration
+    });
+    // Hovers and diagnostics should match what `tsc` and vtsls report for the project
+    if let Some(tsdk) = tsdk {
+        options["typescript"] = serde_json::json!({ "tsdk": tsdk.to_string_lossy() });
+    }
+    options
+}
+
+/// Returns the configuration for vtsls, loading the TypeScript plugin from `plugin_location`
+/// into the same TypeScript as the server, `tsdk`. The TypeScript preferences are only added
+/// for projects the plugin is `install`ed for.
+fn vtsls_configuration(
+    settings: &SvelteSettings,
+    plugin_location: Option<&Path>,
+    tsdk: Option<&Path>,
+    install: bool,
+) -> serde_json::Value {
+    let mut configuration = serde_json::json!({});
+    if let Some(plugin_location) = plugin_location {
+        configuration["vtsls"] = serde_json::json!({
+            "tsserver": {
+                "globalPlugins": [{
+                    "name": TS_PLUGIN_PACKAGE_NAME,
+                    "location": plugin_location.to_string_lossy(),
+                    "enableForWorkspaceTypeScriptVersions": true
+                }]
+            }
+        });
+    }
+    // So both servers agree on the TypeScript, and the plugin gets loaded into it
+    if let Some(tsdk) = tsdk {
+        configuration["typescript"]["tsdk"] = tsdk.to_string_lossy().into();
+    }
 
-fn get_package_path(package_name: &str) -> Result<PathBuf> {
-    let path = env::current_dir()
-        .map_err(|e| e.to_string())?
-        .join("node_modules")
-        .join(package_name);
-    Ok(path)
+    // Imports of components added from `.ts` files come from vtsls, so write them the same
+    // way as the ones added in `.svelte` files
+    if install {
+        for (section, script) in [
+            ("typescript", &settings.typescript),
+            ("javascript", &settings.javascript),
+        ] {
+            if let Some(preferences) = script.preferences.configuration() {
+                configuration[section]["preferences"] = preferences;
+            }
+        }
+    }
+    configuration
 }
 
 impl SvelteExtension {
+    /// Resolves the extension's work directory, which Zed runs the extension in, once. Failing
+    /// to is an error, as every install and server path would otherwise be relative, and
+    /// resolve against the worktree once the server runs.
+    fn resolve_work_dir(&mut self) -> Result<()> {
+        if self.work_dir.as_os_str().is_empty() {
+            self.work_dir = env::current_dir()
+                .map_err(|e| format!("failed to resolve the extension's work directory: {e}"))?;
+        }
+        Ok(())
+    }
+
+    fn package_path(&self, package_name: &str) -> PathBuf {
+        let path = self.work_dir.join("node_modules").join(package_name);
+
+        // Resolve symlinked layouts (e.g. pnpm stores) so tsserver sees a single real path
+        links::resolve(&path)
+    }
+
     fn install_package_if_needed(
         &mut self,
         id: &zed::LanguageServerId,
+        worktree: &zed::Worktree,
+        settings: &SvelteSettings,
         package_name: &str,
     ) -> Result<()> {
+        if let Some(tarball) = settings
+            .server_tarball
+            .as_deref()
+            .filter(|_| package_name == PACKAGE_NAME)
+        {
+            // Installing a package can run its scripts
+            let path = PathBuf::from(worktree.root_path()).join(tarball);
+            if trusted(
+                &worktree.root_path(),
+                settings,
+                "server_tarball",
+                Some(&path),
+            ) {
+                return self.install_server_from_tarball(id, worktree, tarball);
+            }
+        }
+        if package_name == PACKAGE_NAME {
+            // Whatever comes from the registry now replaces the tarball's install
+            fs::remove_file(self.work_dir.join(TARBALL_SPEC_PATH)).ok();
+        }
+
         let installed_version = zed::npm_package_installed_version(package_name)?;
+        let key = (worktree.root_path(), package_name.to_string());
 
-        // If package is already installed in this session, then we won't reinstall it
-        if installed_version.is_some() && self.installed.contains(package_name) {
-            return Ok(());
+        if let Some(installed_version) = &installed_version {
+            let checked_version = self.installed.get(&key).map(String::as_str);
+            if !needs_update_check(settings, installed_version, checked_version) {
+                if checked_version != Some(installed_version) {
+                    debug!(
+                        "Found {package_name}@{installed_version} installed, not updating it while frozen"
+                    );
+                }
+                self.installed.insert(key, installed_version.clone());
+                return Ok(());
+            }
         }
 
         zed::set_language_server_installation_status(
@@ -36,121 +651,1012 @@ impl SvelteExtension {
 
         let latest_version = zed::npm_package_latest_version(package_name)?;
 
-        if installed_version.as_ref() != Some(&latest_version) {
-            println!("Installing {package_name}@{latest_version}...");
+        // Only ever move forward, so a stale registry mirror can't downgrade the install
+        let update = match &installed_version {
+            Some(installed_version) => version::is_newer(&latest_version, installed_version),
+            None => true,
+        };
+
+        let mut version = latest_version.clone();
+        if update {
+            info!("Installing {package_name}@{latest_version}...");
 
             zed::set_language_server_installation_status(
                 id,
                 &zed::LanguageServerInstallationStatus::Downloading,
             );
 
-            if let Err(error) = zed::npm_install_package(package_name, &latest_version) {
-                // If installation failed, but we don't want to error but rather reuse existing version
-                if installed_version.is_none() {
-                    Err(error)?;
+            let retained = package_name == PACKAGE_NAME
+                && installed_version.is_some()
+                && self.retain_previous_install();
+
+            match zed::npm_install_package(package_name, &latest_version) {
+                Ok(()) => {
+                    self.install_errors.remove(package_name);
+                    if settings.clean_up_installs() {
+                        prune::prune_stale_packages(&self.work_dir);
+                    }
+
+                    if let Some(installed_version) = installed_version
+                        .as_ref()
+                        .filter(|_| settings.notify_updates())
+                    {
+                        info!(
+                            "*** Updated {package_name} from {installed_version} to {latest_version}, \
+                             see {CHANGELOG_URL} for what changed ***"
+                        );
+                    }
+                }
+                Err(error) => {
+                    if retained {
+                        self.restore_previous_install();
+                    }
+
+                    let error =
+                        format!("failed to install {package_name}@{latest_version}: {error}");
+                    error!("{error}");
+                    self.install_errors
+                        .insert(package_name.to_string(), error.clone());
+
+                    // If installation failed, but we don't want to error but rather reuse existing version
+                    match installed_version {
+                        Some(installed_version) => version = installed_version,
+                        None => {
+                            zed::set_language_server_installation_status(
+                                id,
+                                &zed::LanguageServerInstallationStatus::Failed(error.clone()),
+                            );
+                            return Err(error);
+                        }
+                    }
                 }
             }
-        } else {
-            println!("Found {package_name}@{latest_version} installed");
+        } else if let Some(installed_version) = installed_version {
+            if installed_version == latest_version {
+                debug!("Found {package_name}@{installed_version} installed");
+            } else {
+                warn!(
+                    "Keeping {package_name}@{installed_version}, the registry reported an older {latest_version}"
+                );
+            }
+            version = installed_version;
         }
 
-        self.installed.insert(package_name.into());
+        self.installed.insert(key, version);
         Ok(())
     }
+
+    /// Installs the server from a local tarball, which is only reinstalled when the setting
+    /// points somewhere else, as there's no registry to tell whether it changed.
+    fn install_server_from_tarball(
+        &mut self,
+        id: &zed::LanguageServerId,
+        worktree: &zed::Worktree,
+        tarball: &str,
+    ) -> Result<()> {
+        if tarball.trim().is_empty() {
+            return Err("`server_tarball` is empty".to_string());
+        }
+        let spec = format!(
+            "file:{}",
+            PathBuf::from(worktree.root_path()).join(tarball).display()
+        );
+        let spec_path = self.work_dir.join(TARBALL_SPEC_PATH);
+
+        let installed_version = zed::npm_package_installed_version(PACKAGE_NAME)?;
+        let installed_spec = fs::read_to_string(&spec_path).ok();
+        if installed_version.is_some() && installed_spec.as_deref() == Some(spec.as_str()) {
+            return Ok(());
+        }
+
+        info!("Installing {PACKAGE_NAME} from {spec}...");
+        zed::set_language_server_installation_status(
+            id,
+            &zed::LanguageServerInstallationStatus::Downloading,
+        );
+        if let Err(error) = zed::npm_install_package(PACKAGE_NAME, &spec) {
+            let error = format!("failed to install {PACKAGE_NAME} from {spec}: {error}");
+            zed::set_language_server_installation_status(
+                id,
+                &zed::LanguageServerInstallationStatus::Failed(error.clone()),
+            );
+            return Err(error);
+        }
+        if let Err(error) = fs::write(&spec_path, &spec) {
+            warn!("Failed to record the installed tarball: {error}");
+        }
+
+        if let Some(version) = zed::npm_package_installed_version(PACKAGE_NAME)? {
+            info!("Installed {PACKAGE_NAME}@{version} from {spec}");
+            self.installed
+                .insert((worktree.root_path(), PACKAGE_NAME.to_string()), version);
+        }
+        Ok(())
+    }
+
+    /// Copies the current install into the previous slot, replacing the one kept there.
+    fn retain_previous_install(&self) -> bool {
+        let previous_dir = self.work_dir.join(PREVIOUS_DIR);
+        match retain_install(&self.work_dir, &previous_dir) {
+            Ok(()) => {
+                info!("Kept the previous install in {}", previous_dir.display());
+                true
+            }
+            Err(error) => {
+                warn!("Failed to keep the previous install: {error}");
+                fs::remove_dir_all(&previous_dir).ok();
+                false
+            }
+        }
+    }
+
+    /// Moves the install in the previous slot back after a failed update.
+    fn restore_previous_install(&self) {
+        let previous_dir = self.work_dir.join(PREVIOUS_DIR);
+        if let Err(error) = restore_install(&self.work_dir, &previous_dir) {
+            error!("Failed to restore the previous install: {error}");
+        }
+        fs::remove_dir_all(&previous_dir).ok();
+    }
+
+    fn server_path(
+        &mut self,
+        id: &zed::LanguageServerId,
+        worktree: &zed::Worktree,
+        settings: &SvelteSettings,
+    ) -> Result<PathBuf> {
+        if let Some(path) = path_override(
+            worktree,
+            settings,
+            "server_path",
+            settings.server_path.as_deref(),
+            SERVER_PATH_ENV_VAR,
+        )? {
+            let remedy = format!(
+                "point `server_path` or {SERVER_PATH_ENV_VAR} at the server's JavaScript \
+                 entrypoint instead, like bin/server.js in {PACKAGE_NAME}"
+            );
+            check_server_path(worktree, &self.work_dir, &path, &remedy)?;
+            return Ok(path);
+        }
+
+        let path = self.installed_server_path(id, worktree, settings)?;
+        check_server_path(worktree, &self.work_dir, &path, "reinstall the server")?;
+        Ok(path)
+    }
+
+    /// Returns the entrypoint of the first copy of the server that applies, short of a path
+    /// override, installing one if needed.
+    fn installed_server_path(
+        &mut self,
+        id: &zed::LanguageServerId,
+        worktree: &zed::Worktree,
+        settings: &SvelteSettings,
+    ) -> Result<PathBuf> {
+        // Updates are skipped while on the previous version, so they can't replace it
+        if settings.use_previous_version() {
+            let package_dir = self
+                .work_dir
+                .join(PREVIOUS_DIR)
+                .join("node_modules")
+                .join(PACKAGE_NAME);
+            let path = self.server_entry(worktree, settings, &package_dir);
+            if fs::metadata(&path).is_err() {
+                return Err(format!(
+                    "`use_previous_version` is set, but no previous version of {PACKAGE_NAME} was kept"
+                ));
+            }
+            info!(
+                "Using the previous version of {PACKAGE_NAME} at {}",
+                path.display()
+            );
+            return Ok(path);
+        }
+
+        // Why the project-local server isn't used, when there is one
+        let mut local_skipped = None;
+        match local_server_package(worktree) {
+            Some(package_dir) if settings.prefer_local() => {
+                let path = self.server_entry(worktree, settings, &package_dir);
+                if settings.trust_workspace() {
+                    info!("Using project-local {PACKAGE_NAME} at {}", path.display());
+                    warn_if_shadowed(worktree, true, "`prefer_local` is on");
+                    return Ok(path);
+                }
+                info!(
+                    "Not running project-local {} because `trust_workspace` is off for this project",
+                    path.display()
+                );
+                local_skipped = Some("`trust_workspace` is off");
+            }
+            Some(_) => local_skipped = Some("`prefer_local` is off"),
+            None => {
+                // Installing into the project ourselves could fight its lockfile, so leave
+                // that to the project's package manager
+                if let Some(version) = project::dependency_version(worktree, PACKAGE_NAME) {
+                    warn!(
+                        "This project depends on {PACKAGE_NAME}@{version} but it isn't installed, \
+                         run your package manager's install (e.g. `npm ci` or `bun install`) to use it"
+                    );
+                }
+            }
+        }
+
+        if let Some(package_dir) = self.modules_dir_package(worktree, settings, PACKAGE_NAME) {
+            return Ok(self.server_entry(worktree, settings, &package_dir));
+        }
+
+        self.install_package_if_needed(id, worktree, settings, PACKAGE_NAME)?;
+        let path = self.server_entry(worktree, settings, &self.package_path(PACKAGE_NAME));
+        if let Some(reason) = local_skipped {
+            warn_if_shadowed(worktree, false, reason);
+        }
+        debug!(
+            "Using extension-managed {PACKAGE_NAME} at {}",
+            path.display()
+        );
+        Ok(path)
+    }
+
+    /// Returns the server entrypoint of the package in `package_dir`: `server_entry` if set,
+    /// otherwise the package's `bin`, falling back to `bin/server.js` if that can't be read.
+    fn server_entry(
+        &self,
+        worktree: &zed::Worktree,
+        settings: &SvelteSettings,
+        package_dir: &Path,
+    ) -> PathBuf {
+        if let Some(entry) = settings
+            .server_entry
+            .as_ref()
+            .filter(|_| trusted(&worktree.root_path(), settings, "server_entry", None))
+        {
+            return package_dir.join(entry);
+        }
+
+        let package_json = read_file(worktree, &self.work_dir, &package_dir.join("package.json"))
+            .and_then(Result::ok)
+            .and_then(|package_json| serde_json::from_str::<serde_json::Value>(&package_json).ok());
+        let bin = package_json.as_ref().and_then(package_bin);
+        package_dir.join(bin.unwrap_or(SERVER_PATH))
+    }
+
+    /// Finds `package_name` in the modules directories set up outside the extension, first
+    /// `modules_dir` and then each entry of `NODE_PATH` in the worktree's shell environment.
+    ///
+    /// Directories the extension can't read are only used when they come from `modules_dir`,
+    /// as then the user asked for them explicitly.
+    fn modules_dir_package(
+        &self,
+        worktree: &zed::Worktree,
+        settings: &SvelteSettings,
+        package_name: &str,
+    ) -> Option<PathBuf> {
+        let node_path = worktree
+            .shell_env()
+            .into_iter()
+            .find(|(key, _)| key == NODE_PATH_ENV_VAR)
+            .map(|(_, node_path)| node_path);
+        let separator = match zed::current_platform().0 {
+            zed::Os::Windows => ';',
+            _ => ':',
+        };
+        let dirs = modules_dirs(
+            Path::new(&worktree.root_path()),
+            settings.modules_dir.as_deref(),
+            node_path.as_deref(),
+            separator,
+        );
+
+        for (source, dir) in dirs {
+            let path = dir.join(package_name);
+            match read_file(worktree, &self.work_dir, &path.join("package.json")) {
+                Some(Ok(_)) => {}
+                None if source == "modules_dir" => {}
+                Some(Err(_)) | None => {
+                    debug!(
+                        "No usable {package_name} in {} from {source}",
+                        dir.display()
+                    );
+                    continue;
+                }
+            }
+            if !trusted(&worktree.root_path(), settings, source, Some(&path)) {
+                continue;
+            }
+            info!("Using {package_name} at {} from {source}", path.display());
+            return Some(path);
+        }
+        None
+    }
+
+    /// Returns the location of the TypeScript plugin, unless the user pointed the extension at
+    /// their own copy. It only gets installed or updated if `install` is set, otherwise this
+    /// returns `None` when no copy is installed yet.
+    fn ts_plugin_path(
+        &mut self,
+        id: &zed::LanguageServerId,
+        worktree: &zed::Worktree,
+        settings: &SvelteSettings,
+        install: bool,
+    ) -> Result<Option<PathBuf>> {
+        if let Some(path) = path_override(
+            worktree,
+            settings,
+            "ts_plugin_path",
+            settings.ts_plugin_path.as_deref(),
+            TS_PLUGIN_PATH_ENV_VAR,
+        )? {
+            return Ok(Some(path));
+        }
+        if let Some(path) = self.modules_dir_package(worktree, settings, TS_PLUGIN_PACKAGE_NAME) {
+            return Ok(Some(path));
+        }
+
+        if install {
+            self.install_package_if_needed(id, worktree, settings, TS_PLUGIN_PACKAGE_NAME)?;
+        } else if zed::npm_package_installed_version(TS_PLUGIN_PACKAGE_NAME)?.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(self.package_path(TS_PLUGIN_PACKAGE_NAME)))
+    }
+
+    /// Returns the TypeScript plugin vtsls gets, which is [`Self::ts_plugin_path`] unless the
+    /// plugin can't load with the project's TypeScript.
+    fn ts_plugin_location(
+        &mut self,
+        id: &zed::LanguageServerId,
+        worktree: &zed::Worktree,
+        settings: &SvelteSettings,
+        install: bool,
+    ) -> Result<Option<PathBuf>> {
+        Ok(self
+            .ts_plugin_path(id, worktree, settings, install)?
+            // A plugin that can't load with the project's TypeScript breaks all of tsserver
+            .filter(|location| ts_plugin_compatible(worktree, location)))
+    }
+
+    /// Returns whether vtsls gets the TypeScript plugin, without installing it.
+    fn ts_plugin_available(
+        &mut self,
+        id: &zed::LanguageServerId,
+        worktree: &zed::Worktree,
+        settings: &SvelteSettings,
+    ) -> bool {
+        self.ts_plugin_location(id, worktree, settings, false)
+            .is_ok_and(|location| location.is_some())
+    }
 }
 
 impl zed::Extension for SvelteExtension {
     fn new() -> Self {
         Self {
-            installed: HashSet::new(),
+            work_dir: PathBuf::new(),
+            installed: HashMap::new(),
+            install_errors: HashMap::new(),
+            launches: HashMap::new(),
+            custom_data_warnings: HashSet::new(),
         }
     }
 
     fn language_server_command(
         &mut self,
         id: &zed::LanguageServerId,
-        _: &zed::Worktree,
+        worktree: &zed::Worktree,
     ) -> Result<zed::Command> {
-        self.install_package_if_needed(id, PACKAGE_NAME)?;
-        self.install_package_if_needed(id, TS_PLUGIN_PACKAGE_NAME)?;
+        self.resolve_work_dir()?;
+        let settings = SvelteSettings::for_worktree(id, worktree)?;
+
+        let root_path = worktree.root_path();
+        let now = Instant::now();
+        let launches = self.launches.entry(root_path.clone()).or_default();
+        if let Err(error) = launches.check(&settings, now) {
+            zed::set_language_server_installation_status(
+                id,
+                &zed::LanguageServerInstallationStatus::Failed(error.clone()),
+            );
+            self.installed.retain(|(root, _), _| root != &root_path);
+            return Err(error);
+        }
+
+        let path = self.server_path(id, worktree, &settings)?;
+        // The plugin is otherwise installed once a TypeScript server asks for its configuration
+        if settings.always_install_ts_plugin() {
+            self.ts_plugin_path(id, worktree, &settings, true)?;
+        }
+
+        // Zed clears the installation status once the server starts, so only the log outlasts
+        // the launch. Logged on every start, so an outdated server never runs without a word
+        let mut install_errors: Vec<&String> = self.install_errors.values().collect();
+        install_errors.sort();
+        for error in install_errors {
+            error!("Starting with the previously installed version, as the last update failed: {error}");
+        }
+
+        if !settings.css.lint.contains_key("unknownAtRules") && project::uses_tailwind(worktree) {
+            info!(
+                "Found a Tailwind CSS config, ignoring unknown at-rules in styles \
+                 (set `css.lint.unknownAtRules` to change this)"
+            );
+        }
+        if settings.selection_range.enable == Some(false) {
+            info!("Selection ranges are off, as `selection_range.enable` is false");
+        }
+
+        match settings.trace.unwrap_or_default() {
+            Trace::Off => {}
+            Trace::Messages => info!(
+                "Tracing is set to messages, see the RPC messages in Zed's language server logs"
+            ),
+            Trace::Verbose => info!("Tracing is set to verbose, the server logs debug output"),
+        }
+
+        let mut node_args = Vec::new();
+        let mut env = Vec::new();
+        let mut env_file_contents = None;
+        if settings.load_env_file() {
+            let env_file = settings.env_file();
+            if !settings.trust_workspace() {
+                // Variables like `NODE_OPTIONS` can make Node.js run the worktree's code
+                info!("Not loading {env_file} because `trust_workspace` is off for this project");
+            } else {
+                match worktree.read_text_file(env_file) {
+                    Ok(contents) => env_file_contents = Some(contents),
+                    Err(error) => warn!("Failed to read {env_file}: {error}"),
+                }
+            }
+        }
+        env.extend(user_env(
+            &worktree.root_path(),
+            &settings,
+            env_file_contents.as_deref(),
+        ));
+
+        // The shim truncates the file, which a project could point at any file of the user's
+        let log_file = settings
+            .log_file
+            .as_ref()
+            .map(|log_file| PathBuf::from(&root_path).join(log_file))
+            .filter(|log_file| trusted(&root_path, &settings, "log_file", Some(log_file)));
+        if let Some(log_file) = log_file {
+            let shim_path = self.work_dir.join(STDERR_TEE_PATH);
+            fs::write(&shim_path, include_str!("stderr_tee.js"))
+                .map_err(|e| format!("failed to write {}: {e}", shim_path.display()))?;
+
+            info!("Copying the server's stderr to {}", log_file.display());
+            node_args.extend([
+                "--require".to_string(),
+                shim_path.to_string_lossy().to_string(),
+            ]);
+            env.push((
+                LOG_FILE_ENV_VAR.to_string(),
+                log_file.to_string_lossy().to_string(),
+            ));
+        }
+
+        // Zed always starts the server in the worktree root, so change directory from within
+        let cwd = settings
+            .cwd
+            .as_ref()
+            .map(|cwd| PathBuf::from(&root_path).join(cwd))
+            .filter(|cwd| trusted(&root_path, &settings, "cwd", Some(cwd)));
+        if let Some(cwd) = cwd {
+            let shim_path = self.work_dir.join(CHDIR_PATH);
+            fs::write(&shim_path, include_str!("chdir.js"))
+                .map_err(|e| format!("failed to write {}: {e}", shim_path.display()))?;
+
+            info!("Running the server from {}", cwd.display());
+            node_args.extend([
+                "--require".to_string(),
+                shim_path.to_string_lossy().to_string(),
+            ]);
+            env.push((CWD_ENV_VAR.to_string(), cwd.to_string_lossy().to_string()));
+        }
+
+        match settings.max_memory_mb {
+            Some(max_memory_mb) if max_memory_mb > 0 => {
+                info!("Limiting the server's heap to {max_memory_mb} MB");
+                node_args.push(format!("--max-old-space-size={max_memory_mb}"));
+            }
+            Some(max_memory_mb) => {
+                warn!("Ignoring `max_memory_mb` of {max_memory_mb}, it must be positive");
+            }
+            None => {}
+        }
 
-        let path = get_package_path(PACKAGE_NAME)?
-            .join("bin/server.js")
-            .to_string_lossy()
-            .to_string();
+        // Volta's `node` shim picks the project's pinned Node.js, which Zed's own would bypass
+        let uses_volta = project::uses_volta(worktree);
+        if uses_volta {
+            info!("Using the project's Volta-pinned Node.js");
+        } else if settings.use_system_node() {
+            info!("Using the node on PATH, as `use_system_node` is set");
+        }
+
+        let wrapper = if settings.wrapper.is_empty()
+            || trusted(&worktree.root_path(), &settings, "wrapper", None)
+        {
+            &settings.wrapper[..]
+        } else {
+            &[]
+        };
 
-        Ok(zed::Command {
-            command: zed::node_binary_path()?,
-            args: vec![path, "--stdio".to_string()],
-            env: Default::default(),
-        })
+        let node_requirement = engines_node(&path);
+        let options = LaunchOptions {
+            node_args: &node_args,
+            node_requirement: node_requirement.as_deref(),
+            prefer_system_node: uses_volta || settings.use_system_node(),
+            wrapper,
+        };
+        let mut command = runtime::server_command(worktree, &path.to_string_lossy(), &options)?;
+        command.env.extend(env);
+        self.launches.entry(root_path).or_default().record(now);
+        Ok(command)
     }
 
     fn language_server_initialization_options(
         &mut self,
-        _: &zed::LanguageServerId,
-        _: &zed::Worktree,
+        id: &zed::LanguageServerId,
+        worktree: &zed::Worktree,
     ) -> Result<Option<serde_json::Value>> {
-        let config = serde_json::json!({
-          "inlayHints": {
-            "parameterNames": {
-              "enabled": "all",
-              "suppressWhenArgumentMatchesName": false
-            },
-            "parameterTypes": {
-              "enabled": true
-            },
-            "variableTypes": {
-              "enabled": true,
-              "suppressWhenTypeMatchesName": false
-            },
-            "propertyDeclarationTypes": {
-              "enabled": true
-            },
-            "functionLikeReturnTypes": {
-              "enabled": true
-            },
-            "enumMemberValues": {
-              "enabled": true
-            }
-          }
-        });
+        self.resolve_work_dir()?;
+        let settings = SvelteSettings::for_worktree(id, worktree)?;
 
-        Ok(Some(serde_json::json!({
-            "provideFormatter": true,
-            "dontFilterIncompleteCompletions": true,
-            "configuration": {
-                "typescript": config,
-                "javascript": config
+        let ts_plugin_available = self.ts_plugin_available(id, worktree, &settings);
+        let configuration = configuration(
+            &settings,
+            worktree,
+            ts_plugin_available,
+            &mut self.custom_data_warnings,
+        );
+
+        let tsdk = tsdk(worktree, &settings);
+        if let Some((tsdk, version)) = &tsdk {
+            match version {
+                Some(version) => info!("Using the project's TypeScript {version}"),
+                None => info!("Using the TypeScript at {}", tsdk.display()),
             }
-        })))
+        }
+        // Zed merges `lsp.svelte-language-server.initialization_options` over these itself
+        Ok(Some(initialization_options(
+            &settings,
+            configuration,
+            tsdk.as_ref().map(|(tsdk, _)| tsdk.as_path()),
+        )))
+    }
+
+    fn language_server_workspace_configuration(
+        &mut self,
+        id: &zed::LanguageServerId,
+        worktree: &zed::Worktree,
+    ) -> Result<Option<serde_json::Value>> {
+        self.resolve_work_dir()?;
+        let settings = SvelteSettings::for_worktree(id, worktree)?;
+
+        // Answer later pulls with what the server was initialized with, raw overrides included
+        let ts_plugin_available = self.ts_plugin_available(id, worktree, &settings);
+        let mut configuration = configuration(
+            &settings,
+            worktree,
+            ts_plugin_available,
+            &mut self.custom_data_warnings,
+        );
+        if let Some(user_configuration) = LspSettings::for_worktree(id.as_ref(), worktree)?
+            .initialization_options
+            .and_then(|mut options| {
+                options
+                    .get_mut("configuration")
+                    .map(serde_json::Value::take)
+            })
+        {
+            settings::merge(&mut configuration, user_configuration);
+        }
+        Ok(Some(configuration))
     }
 
     fn language_server_additional_workspace_configuration(
         &mut self,
-        _id: &zed::LanguageServerId,
+        id: &zed::LanguageServerId,
         target_id: &zed::LanguageServerId,
-        _: &zed::Worktree,
+        worktree: &zed::Worktree,
     ) -> Result<Option<serde_json::Value>> {
-        let plugin_location = get_package_path(TS_PLUGIN_PACKAGE_NAME)?
-            .to_string_lossy()
-            .to_string();
-
+        self.resolve_work_dir()?;
         match target_id.as_ref() {
-            "vtsls" => Ok(Some(serde_json::json!({
-                "vtsls": {
-                    "tsserver": {
-                        "globalPlugins": [{
-                            "name": TS_PLUGIN_PACKAGE_NAME,
-                            "location": plugin_location,
-                            "enableForWorkspaceTypeScriptVersions": true
-                        }]
-                    }
-                },
-            }))),
+            "vtsls" => {
+                let settings = SvelteSettings::for_worktree(id, worktree)?;
+
+                // This is asked for whether or not a Svelte file was opened, so install the
+                // plugin up front for projects using Svelte, but not for every TypeScript project
+                let install = settings.always_install_ts_plugin()
+                    || project::dependency_version(worktree, "svelte").is_some();
+                let plugin_location = self.ts_plugin_location(id, worktree, &settings, install)?;
+                let tsdk = tsdk(worktree, &settings).map(|(tsdk, _)| tsdk);
+                let configuration = vtsls_configuration(
+                    &settings,
+                    plugin_location.as_deref(),
+                    tsdk.as_deref(),
+                    install,
+                );
+
+                Ok((configuration != serde_json::json!({})).then_some(configuration))
+            }
             _ => Ok(None),
         }
     }
 }
 
 zed::register_extension!(SvelteExtension);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROOT: &str = "/projects/app";
+
+    fn workspace_typescript() -> Option<String> {
+        Some("5.5.4".to_string())
+    }
+
+    #[test]
+    fn env_overrides_the_env_file() {
+        let settings = SvelteSettings {
+            env: [("PUBLIC_API".to_string(), "settings".to_string())].into(),
+            ..Default::default()
+        };
+        let env = user_env(
+            ROOT,
+            &settings,
+            Some("PUBLIC_API=file\nDATABASE_URL=postgres://localhost\n"),
+        );
+        assert_eq!(
+            env,
+            BTreeMap::from([
+                (
+                    "DATABASE_URL".to_string(),
+                    "postgres://localhost".to_string()
+                ),
+                ("PUBLIC_API".to_string(), "settings".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn env_from_the_project_needs_trust() {
+        let settings = SvelteSettings {
+            env: [(
+                "NODE_OPTIONS".to_string(),
+                "--require ./evil.js".to_string(),
+            )]
+            .into(),
+            project_keys: ["env".to_string()].into(),
+            ..Default::default()
+        };
+        assert_eq!(user_env(ROOT, &settings, None), BTreeMap::new());
+
+        let settings = SvelteSettings {
+            trust_workspace: Some(true),
+            ..settings
+        };
+        assert_eq!(user_env(ROOT, &settings, None), settings.env);
+    }
+
+    #[test]
+    fn tsdk_uses_the_workspace_typescript_only_when_trusted() {
+        let settings = SvelteSettings::default();
+        assert_eq!(tsdk_location(ROOT, &settings, workspace_typescript), None);
+
+        let settings = SvelteSettings {
+            trust_workspace: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(
+            tsdk_location(ROOT, &settings, workspace_typescript),
+            Some((
+                PathBuf::from("/projects/app/node_modules/typescript/lib"),
+                Some("5.5.4".to_string())
+            ))
+        );
+        assert_eq!(tsdk_location(ROOT, &settings, || None), None);
+    }
+
+    #[test]
+    fn tsdk_can_turn_off_the_workspace_typescript() {
+        let settings = SvelteSettings {
+            trust_workspace: Some(true),
+            use_workspace_typescript: Some(false),
+            ..Default::default()
+        };
+        assert_eq!(tsdk_location(ROOT, &settings, workspace_typescript), None);
+    }
+
+    #[test]
+    fn tsdk_setting_resolves_against_the_worktree() {
+        let settings = SvelteSettings {
+            tsdk: Some("/opt/typescript/lib".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            tsdk_location(ROOT, &settings, workspace_typescript),
+            Some((PathBuf::from("/opt/typescript/lib"), None))
+        );
+
+        let settings = SvelteSettings {
+            tsdk: Some("tools/typescript/lib".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(tsdk_location(ROOT, &settings, workspace_typescript), None);
+
+        let settings = SvelteSettings {
+            trust_workspace: Some(true),
+            ..settings
+        };
+        assert_eq!(
+            tsdk_location(ROOT, &settings, workspace_typescript),
+            Some((PathBuf::from("/projects/app/tools/typescript/lib"), None))
+        );
+    }
+
+    #[test]
+    fn tsdk_setting_from_the_project_needs_trust() {
+        let settings = SvelteSettings {
+            tsdk: Some("/opt/typescript/lib".to_string()),
+            project_keys: ["tsdk".to_string()].into(),
+            ..Default::default()
+        };
+        assert_eq!(tsdk_location(ROOT, &settings, workspace_typescript), None);
+    }
+
+    #[test]
+    fn tsdk_reaches_the_server_and_vtsls() {
+        let settings = SvelteSettings {
+            trust_workspace: Some(true),
+            ..Default::default()
+        };
+        let (tsdk, _) = tsdk_location(ROOT, &settings, workspace_typescript).unwrap();
+        let plugin = Path::new("/extension/node_modules/typescript-svelte-plugin");
+
+        let options = initialization_options(&settings, serde_json::json!({}), Some(&tsdk));
+        assert_eq!(
+            options["typescript"]["tsdk"],
+            "/projects/app/node_modules/typescript/lib"
+        );
+        for install in [true, false] {
+            let configuration = vtsls_configuration(&settings, Some(plugin), Some(&tsdk), install);
+            assert_eq!(
+                configuration["typescript"]["tsdk"],
+                options["typescript"]["tsdk"]
+            );
+            assert_eq!(
+                configuration["vtsls"]["tsserver"]["globalPlugins"][0]["location"],
+                plugin.to_string_lossy().as_ref()
+            );
+        }
+    }
+
+    #[test]
+    fn vtsls_configuration_is_empty_without_a_plugin_or_tsdk() {
+        let configuration = vtsls_configuration(&SvelteSettings::default(), None, None, false);
+        assert_eq!(configuration, serde_json::json!({}));
+    }
+
+    #[test]
+    fn entrypoint_problem_accepts_node_scripts() {
+        let path = Path::new("bin/server.js");
+        let script = "#! /usr/bin/env node\nrequire('../dist/src/server.js').startServer();\n";
+        assert_eq!(entrypoint_problem(path, Ok(script.to_string())), None);
+        assert_eq!(
+            entrypoint_problem(path, Ok("module.exports = {};".to_string())),
+            None
+        );
+    }
+
+    #[test]
+    fn entrypoint_problem_reports_what_cant_run() {
+        let path = Path::new("bin/svelteserver");
+        assert_eq!(
+            entrypoint_problem(
+                path,
+                Ok("#!/bin/sh\nexec node server.js \"$@\"\n".to_string())
+            ),
+            Some(
+                "the server entrypoint bin/svelteserver is a script for /bin/sh, not for Node.js"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            entrypoint_problem(path, Ok(" \n".to_string())),
+            Some("the server entrypoint bin/svelteserver is empty".to_string())
+        );
+        assert_eq!(
+            entrypoint_problem(path, Err("stream did not contain valid UTF-8".to_string())),
+            Some("failed to read the server entrypoint bin/svelteserver: stream did not contain valid UTF-8".to_string())
+        );
+    }
+
+    #[test]
+    fn initialization_options_follow_the_completion_filtering_setting() {
+        let options =
+            initialization_options(&SvelteSettings::default(), serde_json::json!({}), None);
+        assert_eq!(options["dontFilterIncompleteCompletions"], true);
+        assert_eq!(options.get("typescript"), None);
+
+        let settings = SvelteSettings {
+            dont_filter_incomplete_completions: Some(false),
+            ..Default::default()
+        };
+        let options = initialization_options(&settings, serde_json::json!({}), None);
+        assert_eq!(options["dontFilterIncompleteCompletions"], false);
+    }
+
+    #[test]
+    fn initialization_options_tell_the_server_whether_the_project_is_trusted() {
+        let options =
+            initialization_options(&SvelteSettings::default(), serde_json::json!({}), None);
+        assert_eq!(options["isTrusted"], false);
+
+        let settings = SvelteSettings {
+            trust_workspace: Some(true),
+            ..Default::default()
+        };
+        let options = initialization_options(&settings, serde_json::json!({}), None);
+        assert_eq!(options["isTrusted"], true);
+    }
+
+    #[test]
+    fn frozen_installs_are_never_checked_for_updates() {
+        let settings = SvelteSettings {
+            freeze: Some(true),
+            ..Default::default()
+        };
+        assert!(!needs_update_check(&settings, "0.16.0", None));
+        assert!(!needs_update_check(&settings, "0.16.0", Some("0.17.0")));
+    }
+
+    #[test]
+    fn installs_are_checked_for_updates_once_per_version_on_disk() {
+        let settings = SvelteSettings::default();
+        assert!(needs_update_check(&settings, "0.16.0", None));
+        assert!(!needs_update_check(&settings, "0.16.0", Some("0.16.0")));
+        assert!(needs_update_check(&settings, "0.15.0", Some("0.16.0")));
+    }
+
+    #[test]
+    fn modules_dirs_puts_modules_dir_before_node_path() {
+        let dirs = modules_dirs(
+            Path::new(ROOT),
+            Some("vendor/node_modules"),
+            Some("/opt/node_modules::tools/node_modules"),
+            ':',
+        );
+        assert_eq!(
+            dirs,
+            [
+                (
+                    "modules_dir",
+                    PathBuf::from("/projects/app/vendor/node_modules")
+                ),
+                (NODE_PATH_ENV_VAR, PathBuf::from("/opt/node_modules")),
+                (
+                    NODE_PATH_ENV_VAR,
+                    PathBuf::from("/projects/app/tools/node_modules")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn modules_dirs_splits_node_path_at_the_platform_separator() {
+        let dirs = modules_dirs(Path::new(ROOT), None, Some("a;b"), ';');
+        assert_eq!(
+            dirs,
+            [
+                (NODE_PATH_ENV_VAR, PathBuf::from("/projects/app/a")),
+                (NODE_PATH_ENV_VAR, PathBuf::from("/projects/app/b")),
+            ]
+        );
+        assert_eq!(modules_dirs(Path::new(ROOT), None, None, ':'), []);
+    }
+
+    #[test]
+    fn package_bin_reads_both_forms_of_bin() {
+        let package_json = serde_json::json!({
+            "name": "svelte-language-server",
+            "bin": { "svelteserver": "bin/server.js" }
+        });
+        assert_eq!(package_bin(&package_json), Some("bin/server.js"));
+        let package_json = serde_json::json!({ "bin": "dist/server.js" });
+        assert_eq!(package_bin(&package_json), Some("dist/server.js"));
+        assert_eq!(
+            package_bin(&serde_json::json!({ "main": "index.js" })),
+            None
+        );
+    }
+
+    #[test]
+    fn log_file_from_the_project_needs_trust_wherever_it_points() {
+        let settings = SvelteSettings {
+            log_file: Some("/home/me/.bashrc".to_string()),
+            project_keys: ["log_file".to_string()].into(),
+            ..Default::default()
+        };
+        let log_file = Path::new("/home/me/.bashrc");
+        assert!(!trusted(ROOT, &settings, "log_file", Some(log_file)));
+
+        let settings = SvelteSettings {
+            project_keys: Default::default(),
+            ..settings
+        };
+        assert!(trusted(ROOT, &settings, "log_file", Some(log_file)));
+        let inside = Path::new("/projects/app/svelte.log");
+        assert!(!trusted(ROOT, &settings, "log_file", Some(inside)));
+    }
+
+    /// Creates an empty work directory for a test's install fixture.
+    fn work_dir_fixture(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("svelte-install-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write(path: &Path, contents: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn dependencies_of_the_previous_install_survive_an_update() {
+        let work_dir = work_dir_fixture("update");
+        let node_modules = work_dir.join("node_modules");
+        let server_json = Path::new(PACKAGE_NAME).join("package.json");
+        write(&node_modules.join(&server_json), r#"{"version":"0.16.0"}"#);
+        write(
+            &node_modules.join("old-dep/index.js"),
+            "module.exports = 1;",
+        );
+        write(&work_dir.join(LOCKFILE_PATH), r#"{"lockfileVersion":3}"#);
+
+        let previous_dir = work_dir.join(PREVIOUS_DIR);
+        retain_install(&work_dir, &previous_dir).unwrap();
+
+        // The update no longer depends on `old-dep`, which pruning then removes
+        write(&node_modules.join(&server_json), r#"{"version":"0.17.0"}"#);
+        let lockfile = serde_json::json!({
+            "packages": { "": {}, format!("node_modules/{PACKAGE_NAME}"): {} }
+        });
+        write(&work_dir.join(LOCKFILE_PATH), &lockfile.to_string());
+        prune::prune_stale_packages(&work_dir);
+        assert!(!node_modules.join("old-dep").exists());
+
+        let previous_modules = previous_dir.join("node_modules");
+        assert!(previous_modules.join("old-dep/index.js").exists());
+        assert_eq!(
+            fs::read_to_string(previous_modules.join(&server_json)).unwrap(),
+            r#"{"version":"0.16.0"}"#
+        );
+        assert_eq!(
+            fs::read_to_string(previous_dir.join(LOCKFILE_PATH)).unwrap(),
+            r#"{"lockfileVersion":3}"#
+        );
+        fs::remove_dir_all(work_dir).unwrap();
+    }
+
+    #[test]
+    fn a_failed_update_restores_the_previous_install() {
+        let work_dir = work_dir_fixture("restore");
+        let node_modules = work_dir.join("node_modules");
+        write(
+            &node_modules.join("old-dep/index.js"),
+            "module.exports = 1;",
+        );
+
+        let previous_dir = work_dir.join(PREVIOUS_DIR);
+        retain_install(&work_dir, &previous_dir).unwrap();
+        fs::remove_dir_all(node_modules.join("old-dep")).unwrap();
+        write(&node_modules.join("half-installed/index.js"), "");
+
+        restore_install(&work_dir, &previous_dir).unwrap();
+        assert!(node_modules.join("old-dep/index.js").exists());
+        assert!(!node_modules.join("half-installed").exists());
+        fs::remove_dir_all(work_dir).unwrap();
+    }
+}
@@ -0,0 +1,152 @@
+use semver::{BuildMetadata, Version, VersionReq};
+
+/// Returns whether `candidate` is a newer version than `current`, following semver
+/// precedence (so `0.17.0-next.3` is older than `0.17.0`).
+///
+/// Versions that aren't valid semver are only considered newer when they differ.
+pub fn is_newer(candidate: &str, current: &str) -> bool {
+    match (parse(candidate), parse(current)) {
+        (Some(candidate), Some(current)) => candidate > current,
+        _ => candidate.trim() != current.trim(),
+    }
+}
+
+/// Returns whether `version` satisfies the npm-style `range`, like `>=18.0.0` or
+/// `^4.9.0 || ^5.0.0`, or `None` if either can't be parsed.
+pub fn satisfies(version: &str, range: &str) -> Option<bool> {
+    let version = parse(version)?;
+
+    let mut satisfied = false;
+    for alternative in range.split("||") {
+        let requirement = VersionReq::parse(&comparators(alternative)).ok()?;
+        satisfied |= requirement.matches(&version);
+    }
+    Some(satisfied)
+}
+
+/// npm separates the comparators of a range with spaces, while semver expects commas. A
+/// version without an operator is an exact (or x-range) match for npm, but a caret range for
+/// semver, so it gets an `=`.
+fn comparators(range: &str) -> String {
+    let mut comparators = Vec::new();
+    let mut operator = String::new();
+    for token in range.split_whitespace() {
+        // Keep operators written apart from their version, like `>= 18`, with it
+        if token.chars().all(|c| "<>=~^".contains(c)) {
+            operator.push_str(token);
+        } else if operator.is_empty() && token.starts_with(|c: char| c.is_ascii_digit()) {
+            comparators.push(format!("={token}"));
+        } else {
+            comparators.push(format!("{operator}{token}"));
+            operator.clear();
+        }
+    }
+
+    if comparators.is_empty() {
+        "*".to_string()
+    } else {
+        comparators.join(", ")
+    }
+}
+
+/// Parses `version` leniently, accepting a leading `v` and surrounding whitespace.
+///
+/// Build metadata doesn't take part in semver precedence, so it's dropped to keep
+/// `1.0.0+abc` and `1.0.0` comparing equal.
+fn parse(version: &str) -> Option<Version> {
+    let mut version = Version::parse(version.trim().trim_start_matches('v')).ok()?;
+    version.build = BuildMetadata::EMPTY;
+    Some(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn newer_versions_follow_semver_precedence() {
+        assert!(is_newer("0.17.1", "0.17.0"));
+        assert!(is_newer("0.17.10", "0.17.9"));
+        assert!(is_newer("1.0.0", "0.99.99"));
+        assert!(!is_newer("0.17.0", "0.17.0"));
+    }
+
+    #[test]
+    fn older_versions_are_never_newer() {
+        assert!(!is_newer("0.16.9", "0.17.0"));
+        assert!(!is_newer("0.9.0", "0.10.0"));
+    }
+
+    #[test]
+    fn prereleases_are_older_than_their_release() {
+        assert!(is_newer("0.17.0", "0.17.0-next.3"));
+        assert!(!is_newer("0.17.0-next.3", "0.17.0"));
+        assert!(is_newer("0.17.0-next.10", "0.17.0-next.9"));
+        assert!(is_newer("0.17.0-next.1", "0.16.5"));
+    }
+
+    #[test]
+    fn differently_written_versions_are_equal() {
+        assert!(!is_newer("v0.17.0", "0.17.0"));
+        assert!(!is_newer(" 0.17.0\n", "0.17.0"));
+        assert!(!is_newer("0.17.0+build.5", "0.17.0"));
+        assert!(!is_newer("0.17.0", "0.17.0+build.5"));
+        assert!(!is_newer("0.17.0-next.3+abc", "0.17.0-next.3"));
+    }
+
+    #[test]
+    fn ranges_use_npm_semantics() {
+        assert_eq!(satisfies("20.11.0", ">=18.0.0"), Some(true));
+        assert_eq!(satisfies("16.20.0", ">=18.0.0"), Some(false));
+        assert_eq!(satisfies("18.0.0", ">= 18"), Some(true));
+        assert_eq!(satisfies("18.5.0", ">=18.0.0 <20"), Some(true));
+        assert_eq!(satisfies("20.1.0", ">=18.0.0 <20"), Some(false));
+        assert_eq!(satisfies("5.5.4", "~5.5.0"), Some(true));
+        assert_eq!(satisfies("5.6.0", "~5.5.0"), Some(false));
+        assert_eq!(satisfies("v22.5.1", "^22"), Some(true));
+    }
+
+    #[test]
+    fn bare_versions_match_exactly() {
+        assert_eq!(satisfies("1.2.3", "1.2.3"), Some(true));
+        assert_eq!(satisfies("1.5.0", "1.2.3"), Some(false));
+        assert_eq!(satisfies("1.2.9", "1.2"), Some(true));
+        assert_eq!(satisfies("1.3.0", "1.2"), Some(false));
+    }
+
+    #[test]
+    fn alternatives_match_if_any_does() {
+        assert_eq!(satisfies("4.9.5", "^4.9.0 || ^5.0.0"), Some(true));
+        assert_eq!(satisfies("5.5.4", "^4.9.0 || ^5.0.0"), Some(true));
+        assert_eq!(satisfies("4.8.0", "^4.9.0 || ^5.0.0"), Some(false));
+    }
+
+    #[test]
+    fn x_ranges_match_any_version_in_place_of_the_x() {
+        assert_eq!(satisfies("5.5.4", "5.x"), Some(true));
+        assert_eq!(satisfies("5.5.4", "5.5.x"), Some(true));
+        assert_eq!(satisfies("6.0.0", "5.x || 6.x"), Some(true));
+        assert_eq!(satisfies("7.0.0", "5.x || 6.x"), Some(false));
+        assert_eq!(satisfies("7.0.0", "*"), Some(true));
+        assert_eq!(satisfies("7.0.0", ""), Some(true));
+    }
+
+    #[test]
+    fn prereleases_only_match_ranges_that_name_their_version() {
+        assert_eq!(satisfies("5.0.0-beta.1", ">=5.0.0-beta.0"), Some(true));
+        assert_eq!(satisfies("5.0.0-beta.1", ">=4.0.0"), Some(false));
+        assert_eq!(satisfies("5.0.0", ">=5.0.0-beta.0"), Some(true));
+    }
+
+    #[test]
+    fn unparsable_ranges_and_versions_are_unknown() {
+        assert_eq!(satisfies("18.0.0", "not a range"), None);
+        assert_eq!(satisfies("eighteen", ">=18"), None);
+    }
+
+    #[test]
+    fn invalid_versions_are_newer_only_when_different() {
+        assert!(is_newer("latest", "0.17.0"));
+        assert!(!is_newer("nightly", " nightly "));
+    }
+}
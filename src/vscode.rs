@@ -0,0 +1,144 @@
+use crate::{log::warn, settings};
+use zed_extension_api::{
+    self as zed,
+    serde_json::{self, Map, Value},
+};
+
+const SETTINGS_PATH: &str = ".vscode/settings.json";
+
+/// Returns the `svelte.*` settings of the worktree's `.vscode/settings.json`, nested the way
+/// the server reads its configuration (so `svelte.plugin.css.enable` becomes
+/// `{ "svelte": { "plugin": { "css": { "enable": ... } } } }`).
+///
+/// A missing file is skipped silently, while one that can't be parsed is logged and skipped.
+pub fn svelte_settings(worktree: &zed::Worktree) -> Option<Value> {
+    let contents = worktree.read_text_file(SETTINGS_PATH).ok()?;
+    let vscode_settings: Map<String, Value> = match serde_json::from_str(&strip_jsonc(&contents)) {
+        Ok(vscode_settings) => vscode_settings,
+        Err(error) => {
+            warn!("Ignoring {SETTINGS_PATH}, it can't be parsed: {error}");
+            return None;
+        }
+    };
+
+    let mut configuration = Value::Object(Map::new());
+    for (key, value) in vscode_settings {
+        if !key.starts_with("svelte.") {
+            continue;
+        }
+        let nested = key.split('.').rev().fold(
+            value,
+            |value, segment| serde_json::json!({ segment: value }),
+        );
+        settings::merge(&mut configuration, nested);
+    }
+
+    (configuration != serde_json::json!({})).then_some(configuration)
+}
+
+/// Turns VS Code's JSON with comments into plain JSON by dropping comments and trailing
+/// commas, leaving string contents alone.
+pub fn strip_jsonc(jsonc: &str) -> String {
+    let mut json = String::with_capacity(jsonc.len());
+    let mut chars = jsonc.chars().peekable();
+    // Where a comma was held back, as it's only written if a value follows it
+    let mut pending_comma = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                if std::mem::take(&mut pending_comma) {
+                    json.push(',');
+                }
+                json.push(c);
+                while let Some(c) = chars.next() {
+                    json.push(c);
+                    match c {
+                        '\\' => json.extend(chars.next()),
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        json.push(c);
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = None;
+                for c in chars.by_ref() {
+                    if previous == Some('*') && c == '/' {
+                        break;
+                    }
+                    previous = Some(c);
+                }
+            }
+            ',' => pending_comma = true,
+            '}' | ']' => {
+                pending_comma = false;
+                json.push(c);
+            }
+            c if c.is_whitespace() => json.push(c),
+            c => {
+                if std::mem::take(&mut pending_comma) {
+                    json.push(',');
+                }
+                json.push(c);
+            }
+        }
+    }
+
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(jsonc: &str) -> Value {
+        serde_json::from_str(&strip_jsonc(jsonc)).unwrap()
+    }
+
+    #[test]
+    fn drops_comments_and_trailing_commas() {
+        let jsonc = r#"{
+            // Line comment
+            "svelte.plugin.svelte.format.enable": false, /* block
+            comment */ "svelte.enable-ts-plugin": true,
+            "list": [1, 2, /* last */],
+        }"#;
+        assert_eq!(
+            parse(jsonc),
+            serde_json::json!({
+                "svelte.plugin.svelte.format.enable": false,
+                "svelte.enable-ts-plugin": true,
+                "list": [1, 2],
+            })
+        );
+    }
+
+    #[test]
+    fn leaves_comment_markers_and_commas_inside_strings() {
+        let jsonc = r#"{"url": "https://example.com/*not*/a,comment", "quote": "say \"//hi\",", }"#;
+        assert_eq!(
+            parse(jsonc),
+            serde_json::json!({
+                "url": "https://example.com/*not*/a,comment",
+                "quote": "say \"//hi\",",
+            })
+        );
+    }
+
+    #[test]
+    fn keeps_commas_before_values_separated_by_comments() {
+        assert_eq!(
+            parse("[1, // one\n 2 /* two */, 3]"),
+            serde_json::json!([1, 2, 3])
+        );
+    }
+}